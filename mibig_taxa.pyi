@@ -0,0 +1,365 @@
+# Type stubs for the mibig_taxa extension module, see src/lib.rs for the implementation.
+# Keep these in sync when changing the python API.
+
+import os
+from typing import Any, Callable, Iterable, Iterator, Literal, Sequence, overload
+
+_Path = str | os.PathLike[str]
+_ProgressCallback = Callable[[str, int, int], object]
+_OnMissing = Literal["raise", "none", "skip"]
+
+def cache_format_version() -> int: ...
+# args without the program name, defaults to sys.argv[1:]
+def main(args: Sequence[str] | None = None) -> int: ...
+
+class MibigTaxaError(Exception): ...
+
+class TaxonNotFoundError(MibigTaxaError, ValueError):
+    tax_id: int | None
+    # Only set for name and accession lookups respectively
+    name: str
+    accession: str
+
+class InvalidAntismashTaxonError(MibigTaxaError, ValueError):
+    rank: str
+    name: str
+
+class AmbiguousNameError(MibigTaxaError):
+    name: str
+    tax_ids: list[int]
+
+class InvalidArgumentError(MibigTaxaError, ValueError): ...
+
+class DataFileError(MibigTaxaError, OSError): ...
+
+class CacheVersionMismatch(DataFileError):
+    found_version: int
+    supported_version: int
+
+class TaxonEntry:
+    @property
+    def tax_id(self) -> int: ...
+    @property
+    def name(self) -> str: ...
+    @property
+    def species(self) -> str: ...
+    @property
+    def genus(self) -> str: ...
+    @property
+    def family(self) -> str: ...
+    @property
+    def order(self) -> str: ...
+    # The class rank is available as getattr(entry, "class"), it can't be declared here
+    @property
+    def phylum(self) -> str: ...
+    @property
+    def kingdom(self) -> str: ...
+    @property
+    def superkingdom(self) -> str: ...
+    @property
+    def synonyms(self) -> list[str]: ...
+    @property
+    def rank(self) -> str | None: ...
+    @property
+    def parent_id(self) -> int | None: ...
+    def get_antismash_taxon(self) -> str: ...
+
+class TaxonCache:
+    def __init__(self, cachefile: _Path | None = None) -> None: ...
+    def initialise(
+        self,
+        taxdump: _Path,
+        merged_id_dump: _Path,
+        datadir: _Path,
+        nodes_dump: _Path | None = None,
+        names_dump: _Path | None = None,
+        progress_callback: _ProgressCallback | None = None,
+        division_dump: _Path | None = None,
+        gencode_dump: _Path | None = None,
+    ) -> None: ...
+    def initialise_async(self, *args: Any, **kwargs: Any) -> Any: ...
+    def load(
+        self,
+        cachefile: _Path,
+        progress_callback: _ProgressCallback | None = None,
+        streaming: bool = False,
+    ) -> int: ...
+    def load_async(self, *args: Any, **kwargs: Any) -> Any: ...
+    @staticmethod
+    def verify(cachefile: _Path, progress_callback: _ProgressCallback | None = None) -> None: ...
+    def save(
+        self,
+        cachefile: _Path,
+        compression: Literal["gzip", "zstd"] | None = None,
+        level: int | None = None,
+    ) -> int: ...
+    def save_mmap(self, cachefile: _Path) -> int: ...
+    @staticmethod
+    def open_mmap(cachefile: _Path) -> MappedTaxonCache: ...
+    @staticmethod
+    def open_readonly(
+        cachefile: _Path, progress_callback: _ProgressCallback | None = None
+    ) -> TaxonCache: ...
+    @property
+    def frozen(self) -> bool: ...
+    @property
+    def readonly(self) -> bool: ...
+    def freeze(self) -> TaxonCache: ...
+
+    # Changing the contents
+    def prune_to_datadir(self, datadir: _Path) -> int: ...
+    def prune_to_ids(self, ids: Sequence[int]) -> int: ...
+    def merge(
+        self, other: TaxonCache, prefer: Literal["newer", "older", "error"] = "newer"
+    ) -> int: ...
+    def add_entry(
+        self,
+        tax_id: int,
+        name: str,
+        superkingdom: str,
+        kingdom: str,
+        phylum: str,
+        rank: str | None = None,
+        parent_id: int | None = None,
+        class_: str | None = None,
+        order: str | None = None,
+        family: str | None = None,
+        genus: str | None = None,
+        species: str | None = None,
+    ) -> None: ...
+    def override_name(self, tax_id: int, name: str) -> None: ...
+    def is_manual(self, tax_id: int) -> bool: ...
+
+    # Reports and exports
+    def stats(self) -> dict[str, Any]: ...
+    def metadata(self) -> dict[str, str | None]: ...
+    def validate_datadir(self, datadir: _Path) -> ValidationReport: ...
+    def tabulate_antismash_taxa(self, datadir: _Path) -> TaxonTabulation: ...
+    def diff(self, other: TaxonCache) -> CacheDiff: ...
+    def export_json(self, path: _Path, ids: Sequence[int] | None = None) -> int: ...
+    def export_tsv(self, path: _Path, ids: Sequence[int] | None = None) -> int: ...
+    def to_taxopy_db(self, dir: _Path) -> int: ...
+    def to_ete3_sqlite(self, path: _Path) -> int: ...
+    def export_newick(
+        self,
+        root_id: int = 1,
+        ids: Sequence[int] | None = None,
+        labels: Literal["name", "tax_id"] = "name",
+    ) -> str: ...
+
+    # Accessions
+    def load_accession2taxid(
+        self, path: _Path, progress_callback: _ProgressCallback | None = None
+    ) -> int: ...
+    def get_taxid_by_accession(self, accession: str) -> int: ...
+
+    # Lookups by tax ID
+    @overload
+    def get(
+        self, id: int, allow_deprecated: bool = False, on_missing: Literal["raise"] = "raise"
+    ) -> TaxonEntry: ...
+    @overload
+    def get(
+        self, id: int, allow_deprecated: bool = False, *, on_missing: Literal["none"]
+    ) -> TaxonEntry | None: ...
+    @overload
+    def get_name_by_id(
+        self, id: int, allow_deprecated: bool = False, on_missing: Literal["raise"] = "raise"
+    ) -> str: ...
+    @overload
+    def get_name_by_id(
+        self, id: int, allow_deprecated: bool = False, *, on_missing: Literal["none"]
+    ) -> str | None: ...
+    def get_name_by_id_ex(self, id: int) -> tuple[str, int, bool]: ...
+    @overload
+    def get_names_by_ids(
+        self,
+        ids: Sequence[int],
+        allow_deprecated: bool = False,
+        on_missing: Literal["raise", "skip"] = "raise",
+        return_errors: Literal[False] = False,
+    ) -> list[str]: ...
+    @overload
+    def get_names_by_ids(
+        self,
+        ids: Sequence[int],
+        allow_deprecated: bool = False,
+        *,
+        on_missing: Literal["none"],
+        return_errors: Literal[False] = False,
+    ) -> list[str | None]: ...
+    @overload
+    def get_names_by_ids(
+        self,
+        ids: Sequence[int],
+        allow_deprecated: bool = False,
+        on_missing: _OnMissing = "raise",
+        *,
+        return_errors: Literal[True],
+    ) -> tuple[list[str | None], list[tuple[int, str]]]: ...
+    def get_names_by_ids_async(self, *args: Any, **kwargs: Any) -> Any: ...
+    def map_ids_to_names(self, ids: Any, allow_deprecated: bool = False) -> Any: ...
+    def map_ids_to_antismash_taxa(self, ids: Any, allow_deprecated: bool = False) -> Any: ...
+    @overload
+    def get_antismash_taxon(
+        self, id: int, allow_deprecated: bool = False, on_missing: Literal["raise"] = "raise"
+    ) -> str: ...
+    @overload
+    def get_antismash_taxon(
+        self, id: int, allow_deprecated: bool = False, *, on_missing: Literal["none"]
+    ) -> str | None: ...
+    def classify(self, id: int, allow_deprecated: bool = False) -> Classification: ...
+    def get_synonyms(self, id: int, allow_deprecated: bool = False) -> list[str]: ...
+    def get_lineage_string(
+        self,
+        id: int,
+        style: Literal["ncbi", "gtdb"] = "ncbi",
+        delimiter: str | None = None,
+        ranks: Sequence[str] | None = None,
+        allow_deprecated: bool = False,
+    ) -> str: ...
+    def get_rank_by_id(self, id: int, allow_deprecated: bool = False) -> str: ...
+    def get_parent_id(self, id: int, allow_deprecated: bool = False) -> int | None: ...
+    def get_division(self, id: int, allow_deprecated: bool = False) -> str | None: ...
+    def get_genetic_code(
+        self, id: int, mitochondrial: bool = False, allow_deprecated: bool = False
+    ) -> int | None: ...
+    def get_genetic_code_name(
+        self, id: int, mitochondrial: bool = False, allow_deprecated: bool = False
+    ) -> str | None: ...
+    def is_environmental(self, id: int, allow_deprecated: bool = False) -> bool: ...
+    def is_unclassified(self, id: int, allow_deprecated: bool = False) -> bool: ...
+
+    # Lookups by name
+    def get_ids_by_name(self, name: str, include_synonyms: bool = False) -> list[int]: ...
+    def get_antismash_taxon_by_name(
+        self, name: str, allow_deprecated: bool = False, include_synonyms: bool = False
+    ) -> str: ...
+    def search_names(
+        self, query: str, limit: int = 10, fuzzy: bool = True
+    ) -> list[tuple[str, int, float]]: ...
+
+    # Deprecated IDs
+    def resolve_id(self, id: int) -> int: ...
+    def is_deprecated(self, id: int) -> bool: ...
+    @property
+    def deprecated_ids(self) -> dict[int, int]: ...
+
+    # The taxonomic tree
+    def get_lca(self, ids: Sequence[int]) -> int: ...
+    def get_lca_entry(self, ids: Sequence[int]) -> TaxonEntry: ...
+    def is_descendant_of(self, child_id: int, ancestor_id: int) -> bool: ...
+    def get_children(self, id: int, recursive: bool = False) -> list[int]: ...
+    def get_descendants_at_rank(self, root_id: int, rank: str) -> list[int]: ...
+    def get_ancestor_at_rank(self, id: int, rank: str) -> int | None: ...
+    def find_ancestor(
+        self,
+        id: int,
+        predicate_rank: str | None = None,
+        names: str | Iterable[str] | None = None,
+    ) -> int | None: ...
+    def get_species_ancestor(self, id: int) -> int | None: ...
+
+    # antiSMASH taxon mapping and aliases
+    def set_antismash_mapping_rules(self, rules: Sequence[tuple[str, str, str]]) -> None: ...
+    def get_antismash_mapping_rules(self) -> list[tuple[str, str, str]]: ...
+    def load_antismash_exceptions(self, path: _Path) -> int: ...
+    def set_antismash_exceptions(self, exceptions: Sequence[tuple[str, str]]) -> None: ...
+    def get_antismash_exceptions(self) -> list[tuple[str, str]]: ...
+    def add_rank_alias(self, alias: str, rank: str) -> None: ...
+    def add_name_alias(self, alias: str, name: str, rank: str | None = None) -> None: ...
+
+    # Container protocol and copies
+    def __len__(self) -> int: ...
+    def __contains__(self, tax_id: int) -> bool: ...
+    def __getitem__(self, tax_id: int) -> TaxonEntry: ...
+    def __iter__(self) -> Iterator[int]: ...
+    def ids(self) -> Iterator[int]: ...
+    def names(self) -> Iterator[str]: ...
+    def entries(self) -> Iterator[tuple[int, TaxonEntry]]: ...
+    def __copy__(self) -> TaxonCache: ...
+    def __deepcopy__(self, memo: Any) -> TaxonCache: ...
+
+class MappedTaxonCache:
+    @overload
+    def get(
+        self, id: int, allow_deprecated: bool = False, on_missing: Literal["raise"] = "raise"
+    ) -> TaxonEntry: ...
+    @overload
+    def get(
+        self, id: int, allow_deprecated: bool = False, *, on_missing: Literal["none"]
+    ) -> TaxonEntry | None: ...
+    @overload
+    def get_name_by_id(
+        self, id: int, allow_deprecated: bool = False, on_missing: Literal["raise"] = "raise"
+    ) -> str: ...
+    @overload
+    def get_name_by_id(
+        self, id: int, allow_deprecated: bool = False, *, on_missing: Literal["none"]
+    ) -> str | None: ...
+    def get_name_by_id_ex(self, id: int) -> tuple[str, int, bool]: ...
+    @overload
+    def get_antismash_taxon(
+        self, id: int, allow_deprecated: bool = False, on_missing: Literal["raise"] = "raise"
+    ) -> str: ...
+    @overload
+    def get_antismash_taxon(
+        self, id: int, allow_deprecated: bool = False, *, on_missing: Literal["none"]
+    ) -> str | None: ...
+    def __len__(self) -> int: ...
+    def __contains__(self, tax_id: int) -> bool: ...
+    def __getitem__(self, tax_id: int) -> TaxonEntry: ...
+
+class ValidationReport:
+    @property
+    def checked(self) -> int: ...
+    @property
+    def missing(self) -> list[tuple[str, int]]: ...
+    @property
+    def deprecated(self) -> list[tuple[str, int, int]]: ...
+    @property
+    def name_mismatches(self) -> list[tuple[str, int, str, str]]: ...
+    @property
+    def ok(self) -> bool: ...
+
+class TaxonTabulation:
+    @property
+    def entries(self) -> int: ...
+    @property
+    def counts(self) -> dict[str, int]: ...
+    @property
+    def unclassifiable(self) -> list[tuple[str, int, str, str]]: ...
+    @property
+    def missing(self) -> list[tuple[str, int]]: ...
+
+class CacheDiff:
+    @property
+    def added(self) -> list[int]: ...
+    @property
+    def removed(self) -> list[int]: ...
+    @property
+    def renamed(self) -> list[tuple[int, str, str]]: ...
+    @property
+    def reparented(self) -> list[tuple[int, int, int]]: ...
+    @property
+    def empty(self) -> bool: ...
+
+class Classification:
+    @property
+    def tax_id(self) -> int: ...
+    @property
+    def taxon(self) -> str | None: ...
+    @property
+    def rank(self) -> str: ...
+    @property
+    def name(self) -> str: ...
+    @property
+    def by_rule(self) -> bool: ...
+    @property
+    def lineage(self) -> list[tuple[str, str]]: ...
+    @property
+    def nearest_mappable_ancestor(self) -> int | None: ...
+    @property
+    def ancestor_taxon(self) -> str | None: ...
+    @property
+    def mapped(self) -> bool: ...
@@ -0,0 +1,310 @@
+//! Nucleotide accession to tax ID lookups from NCBI accession2taxid files
+//!
+//! The files have hundreds of millions of lines, so the index stores all accessions sorted in one
+//! buffer instead of a map with one allocation per accession. NCBI ships the files gzip
+//! compressed, they are decompressed while reading.
+
+use std::cmp::Ordering;
+use std::io::{self, BufRead, Read};
+
+use mibig_taxa::MibigTaxonError;
+
+/// Entries per block of offsets
+const BLOCK_SIZE: usize = 256;
+
+/// Longest accession accepted, so the offsets within a block fit in a u32
+const MAX_KEY_LEN: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AccessionIndex {
+    /// All accessions without version, sorted and concatenated
+    text: Vec<u8>,
+    /// Offset in text of the first accession of each block of BLOCK_SIZE accessions
+    block_starts: Vec<u64>,
+    /// End offset of each accession, relative to the start of its block
+    ends: Vec<u32>,
+    tax_ids: Vec<u32>,
+}
+
+impl AccessionIndex {
+    pub fn len(&self) -> usize {
+        self.tax_ids.len()
+    }
+
+    /// Look up an accession, with or without version
+    pub fn get(&self, accession: &str) -> Option<i64> {
+        let key = strip_version(accession).as_bytes();
+        let mut low = 0;
+        let mut high = self.len();
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.key(mid).cmp(key) {
+                Ordering::Equal => return Some(i64::from(self.tax_ids[mid])),
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+
+    fn key(&self, index: usize) -> &[u8] {
+        let block_start = self.block_starts[index / BLOCK_SIZE] as usize;
+        let start = if index.is_multiple_of(BLOCK_SIZE) {
+            0
+        } else {
+            self.ends[index - 1] as usize
+        };
+        &self.text[block_start + start..block_start + self.ends[index] as usize]
+    }
+
+    /// Read an accession2taxid file into a sorted index of its own, to merge() into another one
+    ///
+    /// Of several lines for an accession, the last one wins.
+    pub fn read_sorted(input: impl Read) -> Result<AccessionIndex, MibigTaxonError> {
+        let unsorted = AccessionIndex::read_unsorted(input)?;
+        if unsorted.len() > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "too many accessions").into());
+        }
+        // Stable sort, so the last of several lines for an accession comes last
+        let mut order: Vec<u32> = (0..unsorted.len() as u32).collect();
+        order.sort_by(|a, b| unsorted.key(*a as usize).cmp(unsorted.key(*b as usize)));
+
+        let mut index = AccessionIndex::default();
+        index.text.reserve_exact(unsorted.text.len());
+        index.ends.reserve_exact(unsorted.len());
+        index.tax_ids.reserve_exact(unsorted.len());
+        for (position, entry) in order.iter().enumerate() {
+            let entry = *entry as usize;
+            let next = order.get(position + 1).map(|next| *next as usize);
+            if next.is_some_and(|next| unsorted.key(next) == unsorted.key(entry)) {
+                continue;
+            }
+            index.push(unsorted.key(entry), unsorted.tax_ids[entry]);
+        }
+        index.shrink_to_fit();
+        Ok(index)
+    }
+
+    /// Merge the accessions of a sorted index into this one, replacing existing ones
+    ///
+    /// The merge happens in place from the back, so the only extra memory needed is the one for
+    /// the added accessions.
+    pub fn merge(&mut self, added: AccessionIndex) {
+        // Count the replaced accessions first, to know where the merged ones end
+        let mut replaced = 0;
+        let mut replaced_text = 0;
+        let (mut existing, mut new) = (0, 0);
+        while existing < self.len() && new < added.len() {
+            match self.key(existing).cmp(added.key(new)) {
+                Ordering::Less => existing += 1,
+                Ordering::Greater => new += 1,
+                Ordering::Equal => {
+                    replaced += 1;
+                    replaced_text += self.key(existing).len();
+                    existing += 1;
+                    new += 1;
+                }
+            }
+        }
+        let len = self.len() + added.len() - replaced;
+        let text_len = self.text.len() + added.text.len() - replaced_text;
+
+        // Store the length of each accession while moving them, and the offsets again afterwards
+        for index in (0..self.len()).rev() {
+            if !index.is_multiple_of(BLOCK_SIZE) {
+                self.ends[index] -= self.ends[index - 1];
+            }
+        }
+        let (mut existing, mut new) = (self.len(), added.len());
+        let mut existing_end = self.text.len();
+        let mut end = text_len;
+        self.text.resize(text_len, 0);
+        self.ends.resize(len, 0);
+        self.tax_ids.resize(len, 0);
+        for position in (0..len).rev() {
+            // Once all added accessions are placed, the remaining ones are in place already
+            if new == 0 {
+                break;
+            }
+            let ordering = match existing {
+                0 => Ordering::Less,
+                _ => {
+                    let key_len = self.ends[existing - 1] as usize;
+                    let key = &self.text[existing_end - key_len..existing_end];
+                    key.cmp(added.key(new - 1))
+                }
+            };
+            if ordering == Ordering::Greater {
+                let key_len = self.ends[existing - 1] as usize;
+                self.text
+                    .copy_within(existing_end - key_len..existing_end, end - key_len);
+                self.ends[position] = key_len as u32;
+                self.tax_ids[position] = self.tax_ids[existing - 1];
+                existing -= 1;
+                existing_end -= key_len;
+                end -= key_len;
+            } else {
+                if ordering == Ordering::Equal {
+                    // Replaced by the added accession
+                    existing_end -= self.ends[existing - 1] as usize;
+                    existing -= 1;
+                }
+                let key = added.key(new - 1);
+                self.text[end - key.len()..end].copy_from_slice(key);
+                self.ends[position] = key.len() as u32;
+                self.tax_ids[position] = added.tax_ids[new - 1];
+                new -= 1;
+                end -= key.len();
+            }
+        }
+        drop(added);
+
+        self.block_starts.clear();
+        let mut offset = 0;
+        let mut block_end = 0;
+        for index in 0..len {
+            if index.is_multiple_of(BLOCK_SIZE) {
+                self.block_starts.push(offset);
+                block_end = 0;
+            }
+            offset += u64::from(self.ends[index]);
+            block_end += self.ends[index];
+            self.ends[index] = block_end;
+        }
+        self.shrink_to_fit();
+    }
+
+    /// Read the accessions of an accession2taxid file in file order
+    fn read_unsorted(input: impl Read) -> Result<AccessionIndex, MibigTaxonError> {
+        let mut index = AccessionIndex::default();
+        let mut reader = io::BufReader::new(input);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            let mut parts = line.split('\t');
+            let (accession, tax_id) = match (parts.next(), parts.nth(1)) {
+                (Some(accession), Some(tax_id)) => (accession, tax_id.trim()),
+                _ => continue,
+            };
+            // Skip the header
+            if tax_id == "taxid" {
+                continue;
+            }
+            let tax_id: u32 = tax_id.parse()?;
+            let accession = strip_version(accession);
+            if accession.len() > MAX_KEY_LEN {
+                let message = format!("accession longer than {} bytes", MAX_KEY_LEN);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message).into());
+            }
+            index.push(accession.as_bytes(), tax_id);
+        }
+        Ok(index)
+    }
+
+    fn push(&mut self, key: &[u8], tax_id: u32) {
+        let block_start = if self.len().is_multiple_of(BLOCK_SIZE) {
+            self.block_starts.push(self.text.len() as u64);
+            self.text.len()
+        } else {
+            *self.block_starts.last().unwrap() as usize
+        };
+        self.text.extend_from_slice(key);
+        self.ends.push((self.text.len() - block_start) as u32);
+        self.tax_ids.push(tax_id);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.text.shrink_to_fit();
+        self.block_starts.shrink_to_fit();
+        self.ends.shrink_to_fit();
+        self.tax_ids.shrink_to_fit();
+    }
+}
+
+fn strip_version(accession: &str) -> &str {
+    accession.split('.').next().unwrap_or(accession).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "accession\taccession.version\ttaxid\tgi\n";
+
+    fn sorted(lines: &str) -> AccessionIndex {
+        AccessionIndex::read_sorted(format!("{}{}", HEADER, lines).as_bytes()).unwrap()
+    }
+
+    fn index(lines: &str) -> AccessionIndex {
+        let mut index = AccessionIndex::default();
+        index.merge(sorted(lines));
+        index
+    }
+
+    fn lines(accessions: impl Iterator<Item = u32>, tax_id: impl Fn(u32) -> u32) -> String {
+        accessions
+            .map(|n| format!("AB{:06}\tAB{:06}.1\t{}\t0\n", n, n, tax_id(n)))
+            .collect()
+    }
+
+    #[test]
+    fn lookups() {
+        let index = index("CP000001\tCP000001.1\t5062\t1\nAL645882\tAL645882.2\t100226\t2\n");
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get("AL645882"), Some(100226));
+        assert_eq!(index.get("AL645882.2"), Some(100226));
+        assert_eq!(index.get("CP000001.1"), Some(5062));
+        assert_eq!(index.get("CP000002"), None);
+    }
+
+    #[test]
+    fn last_duplicate_wins() {
+        let index = index("AL645882\tAL645882.1\t1902\t1\nAL645882\tAL645882.2\t100226\t2\n");
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.get("AL645882"), Some(100226));
+    }
+
+    #[test]
+    fn merge_replaces_existing() {
+        let mut index = index("AB000001\tAB000001.1\t1\t1\nCP000001\tCP000001.1\t2\t2\n");
+        index.merge(sorted(
+            "CP000001\tCP000001.1\t3\t3\nBA000001\tBA000001.1\t4\t4\n",
+        ));
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get("AB000001"), Some(1));
+        assert_eq!(index.get("BA000001"), Some(4));
+        assert_eq!(index.get("CP000001"), Some(3));
+    }
+
+    #[test]
+    fn merge_across_blocks() {
+        // Even accessions first, then every third one, replacing some of the even ones
+        let mut index = index(&lines((0..2000).step_by(2), |n| n));
+        index.merge(sorted(&lines((0..2000).step_by(3), |n| n + 1)));
+        let expected = (0..2000).filter(|n| n % 2 == 0 || n % 3 == 0).count();
+        assert_eq!(index.len(), expected);
+        assert_eq!(index.block_starts.len(), expected.div_ceil(BLOCK_SIZE));
+        for n in 0..2000 {
+            let tax_id = match (n % 2, n % 3) {
+                (_, 0) => Some(i64::from(n + 1)),
+                (0, _) => Some(i64::from(n)),
+                _ => None,
+            };
+            assert_eq!(index.get(&format!("AB{:06}.1", n)), tax_id, "AB{:06}", n);
+        }
+
+        // Merging an empty index changes nothing
+        index.merge(AccessionIndex::default());
+        assert_eq!(index.len(), expected);
+        assert_eq!(index.get("AB001996"), Some(1996));
+    }
+
+    #[test]
+    fn invalid_tax_id() {
+        let input = format!("{}AL645882\tAL645882.2\tnot a number\t2\n", HEADER);
+        assert!(AccessionIndex::read_sorted(input.as_bytes()).is_err());
+    }
+}
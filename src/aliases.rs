@@ -0,0 +1,122 @@
+//! Rank and taxon name aliases, so caches built from different taxdump versions behave the same
+//!
+//! Aliases map older names to the ones used by current taxdumps.
+
+use std::collections::HashMap;
+
+/// (alias, rank) pairs for renamed NCBI ranks
+const RANK_ALIASES: [(&str, &str); 1] = [("domain", "superkingdom")];
+
+/// (rank, alias, name) triples for renamed taxa
+///
+/// Mostly the 2021 renaming of prokaryote phyla to names ending in -ota.
+const NAME_ALIASES: [(&str, &str, &str); 21] = [
+    ("phylum", "Acidobacteria", "Acidobacteriota"),
+    ("phylum", "Actinobacteria", "Actinomycetota"),
+    ("phylum", "Aquificae", "Aquificota"),
+    ("phylum", "Bacteroidetes", "Bacteroidota"),
+    ("phylum", "Chlamydiae", "Chlamydiota"),
+    ("phylum", "Chloroflexi", "Chloroflexota"),
+    ("phylum", "Crenarchaeota", "Thermoproteota"),
+    ("phylum", "Cyanobacteria", "Cyanobacteriota"),
+    ("phylum", "Deinococcus-Thermus", "Deinococcota"),
+    ("phylum", "Euryarchaeota", "Methanobacteriota"),
+    ("phylum", "Firmicutes", "Bacillota"),
+    ("phylum", "Fusobacteria", "Fusobacteriota"),
+    ("phylum", "Planctomycetes", "Planctomycetota"),
+    ("phylum", "Proteobacteria", "Pseudomonadota"),
+    ("phylum", "Spirochaetes", "Spirochaetota"),
+    ("phylum", "Tenericutes", "Mycoplasmatota"),
+    ("phylum", "Thaumarchaeota", "Nitrososphaerota"),
+    ("phylum", "Thermotogae", "Thermotogota"),
+    ("phylum", "Verrucomicrobia", "Verrucomicrobiota"),
+    ("class", "Actinobacteria", "Actinomycetes"),
+    ("order", "Streptomycetales", "Kitasatosporales"),
+];
+
+/// Name alias added with add_name_alias(), as (alias, name, rank)
+pub(crate) type NameAliasSpec = (String, String, Option<String>);
+
+/// The built-in aliases plus the ones added from python
+#[derive(Debug, Clone)]
+pub(crate) struct Aliases {
+    ranks: HashMap<String, String>,
+    /// Keyed by (rank, lowercase alias), with an empty rank for aliases valid at any rank
+    names: HashMap<(String, String), String>,
+    custom_ranks: Vec<(String, String)>,
+    custom_names: Vec<NameAliasSpec>,
+}
+
+impl Default for Aliases {
+    fn default() -> Aliases {
+        let mut aliases = Aliases {
+            ranks: HashMap::new(),
+            names: HashMap::new(),
+            custom_ranks: Vec::new(),
+            custom_names: Vec::new(),
+        };
+        for (alias, rank) in RANK_ALIASES.iter() {
+            aliases.ranks.insert(alias.to_string(), rank.to_string());
+        }
+        for (rank, alias, name) in NAME_ALIASES.iter() {
+            aliases
+                .names
+                .insert((rank.to_string(), alias.to_lowercase()), name.to_string());
+        }
+        aliases
+    }
+}
+
+impl Aliases {
+    /// Get the current name of a rank
+    pub fn rank<'a>(&'a self, rank: &'a str) -> &'a str {
+        self.ranks.get(rank).map_or(rank, String::as_str)
+    }
+
+    /// Get the current name of a taxon at rank
+    pub fn name<'a>(&'a self, rank: &str, name: &'a str) -> &'a str {
+        let alias = name.to_lowercase();
+        self.names
+            .get(&(self.rank(rank).to_string(), alias.clone()))
+            .or_else(|| self.names.get(&(String::new(), alias)))
+            .map_or(name, String::as_str)
+    }
+
+    /// Get all other names of a taxon, as (lowercase name, rank) pairs
+    ///
+    /// The rank is None for names that aren't limited to a rank.
+    pub fn other_names(&self, name: &str) -> Vec<(String, Option<&str>)> {
+        let key = name.to_lowercase();
+        let mut others = Vec::new();
+        for ((rank, alias), current) in &self.names {
+            let rank = if rank.is_empty() {
+                None
+            } else {
+                Some(rank.as_str())
+            };
+            if *alias == key {
+                others.push((current.to_lowercase(), rank));
+            } else if current.to_lowercase() == key {
+                others.push((alias.clone(), rank));
+            }
+        }
+        others.sort_unstable();
+        others
+    }
+
+    pub fn add_rank(&mut self, alias: String, rank: String) {
+        self.ranks.insert(alias.clone(), rank.clone());
+        self.custom_ranks.push((alias, rank));
+    }
+
+    pub fn add_name(&mut self, alias: String, name: String, rank: Option<String>) {
+        let key = (rank.clone().unwrap_or_default(), alias.to_lowercase());
+        self.names.insert(key, name.clone());
+        self.custom_names.push((alias, name, rank));
+    }
+
+    /// The aliases added on top of the built-in ones, in the order they were added
+    pub fn custom(&self) -> (Vec<(String, String)>, Vec<NameAliasSpec>) {
+        (self.custom_ranks.clone(), self.custom_names.clone())
+    }
+}
@@ -0,0 +1,190 @@
+//! Reading dump files from taxdump archives like new_taxdump.tar.gz
+//!
+//! The tar format is simple enough to read here, gzip compressed archives are decompressed with
+//! compression::open_decompressed().
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::compression::open_decompressed;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Whether a file is a tar archive, optionally gzip compressed
+pub(crate) fn is_archive(path: &Path) -> io::Result<bool> {
+    let mut start = Vec::with_capacity(BLOCK_SIZE);
+    open_decompressed(fs::File::open(path)?)?
+        .take(BLOCK_SIZE as u64)
+        .read_to_end(&mut start)?;
+    Ok(start.len() == BLOCK_SIZE && start[257..262] == *b"ustar")
+}
+
+/// Open a member of an archive by file name, returning a reader for its contents and its size
+///
+/// Members are matched by their file name only, so "merged.dmp" is found as "./merged.dmp" too.
+/// Tar archives have no index, so this reads the archive from the start up to the member, and the
+/// contents are streamed from the archive rather than read into memory.
+pub(crate) fn open_member(path: &Path, wanted: &str) -> io::Result<(impl Read, u64)> {
+    let archive = open_decompressed(fs::File::open(path)?)?;
+    find_member(archive, wanted)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found in {}", wanted, path.display()),
+        )
+    })
+}
+
+/// Skip to a member of a decompressed archive, see open_member()
+fn find_member<R: Read>(
+    mut archive: R,
+    wanted: &str,
+) -> io::Result<Option<(MemberReader<R>, u64)>> {
+    let mut header = [0; BLOCK_SIZE];
+    loop {
+        archive.read_exact(&mut header)?;
+        // The archive ends with empty blocks
+        if header.iter().all(|byte| *byte == 0) {
+            return Ok(None);
+        }
+        let size = parse_octal(&header[124..136])?;
+        let padded_size = size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64;
+        let name = member_name(&header);
+        let file_name = Path::new(&name)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+
+        // Type "0" and NUL are regular files, everything else e.g. directories or pax headers
+        let is_file = header[156] == b'0' || header[156] == 0;
+        if is_file && file_name.as_deref() == Some(wanted) {
+            return Ok(Some((MemberReader(archive.take(size)), size)));
+        }
+        io::copy(&mut (&mut archive).take(padded_size), &mut io::sink())?;
+    }
+}
+
+/// Reader for the contents of a member, failing if the archive ends before the member does
+struct MemberReader<R>(io::Take<R>);
+
+impl<R: Read> Read for MemberReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.0.read(buf)?;
+        if count == 0 && !buf.is_empty() && self.0.limit() > 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(count)
+    }
+}
+
+/// The member name, including the ustar prefix for long paths
+fn member_name(header: &[u8; BLOCK_SIZE]) -> String {
+    let field = |bytes: &[u8]| {
+        let end = bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).to_string()
+    };
+    let name = field(&header[..100]);
+    let prefix = field(&header[345..500]);
+    if header[257..262] == *b"ustar" && !prefix.is_empty() {
+        format!("{}/{}", prefix, name)
+    } else {
+        name
+    }
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<u64> {
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(digits, 8).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid size {:?} in tar header", digits),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(name: &str, size: usize, kind: u8) -> [u8; BLOCK_SIZE] {
+        let mut header = [0; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header
+    }
+
+    /// A tar archive with a directory and the given files, in order
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = header("./", 0, b'5').to_vec();
+        for (name, contents) in files {
+            data.extend_from_slice(&header(name, contents.len(), b'0'));
+            data.extend_from_slice(contents);
+            let padding = (BLOCK_SIZE - contents.len() % BLOCK_SIZE) % BLOCK_SIZE;
+            data.resize(data.len() + padding, 0);
+        }
+        data.resize(data.len() + 2 * BLOCK_SIZE, 0);
+        data
+    }
+
+    fn read_member(archive: &[u8], wanted: &str) -> io::Result<Option<(Vec<u8>, u64)>> {
+        let (mut reader, size) = match find_member(archive, wanted)? {
+            Some(member) => member,
+            None => return Ok(None),
+        };
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+        Ok(Some((contents, size)))
+    }
+
+    #[test]
+    fn find_members() {
+        let nodes = vec![b'n'; 1000];
+        let merged = b"12345\t|\t100226\t|\n";
+        let data = archive(&[("./nodes.dmp", &nodes), ("./merged.dmp", merged)]);
+
+        assert_eq!(
+            read_member(&data, "nodes.dmp").unwrap(),
+            Some((nodes, 1000))
+        );
+        assert_eq!(
+            read_member(&data, "merged.dmp").unwrap(),
+            Some((merged.to_vec(), merged.len() as u64))
+        );
+        assert_eq!(read_member(&data, "names.dmp").unwrap(), None);
+    }
+
+    #[test]
+    fn directories_are_not_members() {
+        let mut data = header("names.dmp/", 0, b'5').to_vec();
+        data.extend_from_slice(&archive(&[("names.dmp/names.dmp", b"names")]));
+        let (names, _) = read_member(&data, "names.dmp").unwrap().unwrap();
+        assert_eq!(names, b"names");
+    }
+
+    #[test]
+    fn truncated_member() {
+        let data = archive(&[("nodes.dmp", &[b'n'; 2000])]);
+        let err = read_member(&data[..3 * BLOCK_SIZE], "nodes.dmp").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn invalid_size() {
+        let mut data = archive(&[("nodes.dmp", b"1\t|\t1\t|\n")]);
+        data[BLOCK_SIZE + 124..BLOCK_SIZE + 135].copy_from_slice(b"not a size!");
+        let err = read_member(&data, "nodes.dmp").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn long_member_names() {
+        let mut member = header("names.dmp", 5, b'0');
+        member[345..352].copy_from_slice(b"taxdump");
+        assert_eq!(member_name(&member), "taxdump/names.dmp");
+    }
+}
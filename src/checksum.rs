@@ -0,0 +1,325 @@
+//! Checksums to detect truncated or corrupted cache files
+//!
+//! save() writes the checksum as the last field of the JSON object, computed over everything in
+//! front of it. Other readers of the format just see one more field.
+
+use std::io::{self, Read};
+
+/// Start of the checksum field, followed by 16 hex digits and the closing `"}`
+const TRAILER_START: &[u8] = br#","checksum":"xxh64:"#;
+const TRAILER_LEN: usize = TRAILER_START.len() + 16 + 2;
+
+/// Result of checking the checksum of a cache file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Checksum {
+    Valid,
+    Mismatch,
+    /// Files written by older versions of this library or by mibig_taxa itself
+    Missing,
+}
+
+/// Add the checksum field to a serialized JSON object
+pub(crate) fn append(json_data: &mut Vec<u8>) {
+    // Replace the closing brace of the object with the checksum field
+    json_data.pop();
+    let hash = xxh64(json_data);
+    json_data.extend_from_slice(TRAILER_START);
+    json_data.extend_from_slice(format!("{:016x}\"}}", hash).as_bytes());
+}
+
+/// Check the checksum of a whole cache file
+pub(crate) fn check(data: &[u8]) -> Checksum {
+    if data.len() < TRAILER_LEN {
+        return Checksum::Missing;
+    }
+    let (body, trailer) = data.split_at(data.len() - TRAILER_LEN);
+    check_trailer(trailer, xxh64(body))
+}
+
+fn check_trailer(trailer: &[u8], hash: u64) -> Checksum {
+    let expected = match parse_trailer(trailer) {
+        Some(expected) => expected,
+        None => return Checksum::Missing,
+    };
+    if expected == hash {
+        Checksum::Valid
+    } else {
+        Checksum::Mismatch
+    }
+}
+
+fn parse_trailer(trailer: &[u8]) -> Option<u64> {
+    let digits = trailer.strip_prefix(TRAILER_START)?.strip_suffix(b"\"}")?;
+    u64::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+/// Computes the checksum of everything read through it, for files that are parsed while reading
+///
+/// The last bytes are held back until the end of the file, as they might be the checksum field.
+pub(crate) struct ChecksumReader<R> {
+    inner: R,
+    hasher: Xxh64,
+    tail: Vec<u8>,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(inner: R) -> ChecksumReader<R> {
+        ChecksumReader {
+            inner,
+            hasher: Xxh64::new(0),
+            tail: Vec::with_capacity(2 * TRAILER_LEN),
+        }
+    }
+
+    /// Check the checksum, after everything has been read
+    pub fn finish(self) -> Checksum {
+        if self.tail.len() < TRAILER_LEN {
+            return Checksum::Missing;
+        }
+        check_trailer(&self.tail, self.hasher.digest())
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.tail.extend_from_slice(&buf[..count]);
+        if self.tail.len() > TRAILER_LEN {
+            let hashed = self.tail.len() - TRAILER_LEN;
+            self.hasher.update(&self.tail[..hashed]);
+            self.tail.drain(..hashed);
+        }
+        Ok(count)
+    }
+}
+
+/// Computes the checksum of everything read through it, for input files like merged.dmp
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    hasher: Xxh64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> HashingReader<R> {
+        HashingReader {
+            inner,
+            hasher: Xxh64::new(0),
+        }
+    }
+
+    /// Read the rest of the input and get its checksum, formatted like the one of cache files
+    pub fn finish(mut self) -> io::Result<String> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(format!("xxh64:{:016x}", self.hasher.digest()))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.hasher.update(&buf[..count]);
+        Ok(count)
+    }
+}
+
+const PRIME_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+/// Hash of data with seed 0
+pub(crate) fn xxh64(data: &[u8]) -> u64 {
+    let mut hasher = Xxh64::new(0);
+    hasher.update(data);
+    hasher.digest()
+}
+
+/// Streaming XXH64, see https://github.com/Cyan4973/xxHash/blob/dev/doc/xxhash_spec.md
+pub(crate) struct Xxh64 {
+    seed: u64,
+    accumulators: [u64; 4],
+    /// Input not yet processed, less than one 32 byte stripe
+    buffer: [u8; 32],
+    buffered: usize,
+    total_len: u64,
+}
+
+impl Xxh64 {
+    pub fn new(seed: u64) -> Xxh64 {
+        Xxh64 {
+            seed,
+            accumulators: [
+                seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+                seed.wrapping_add(PRIME_2),
+                seed,
+                seed.wrapping_sub(PRIME_1),
+            ],
+            buffer: [0; 32],
+            buffered: 0,
+            total_len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+
+        if self.buffered > 0 {
+            let count = data.len().min(32 - self.buffered);
+            self.buffer[self.buffered..self.buffered + count].copy_from_slice(&data[..count]);
+            self.buffered += count;
+            data = &data[count..];
+            if self.buffered < 32 {
+                return;
+            }
+            let stripe = self.buffer;
+            self.process_stripe(&stripe);
+            self.buffered = 0;
+        }
+
+        let mut stripes = data.chunks_exact(32);
+        for stripe in &mut stripes {
+            self.process_stripe(stripe);
+        }
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    pub fn digest(&self) -> u64 {
+        let mut hash = if self.total_len >= 32 {
+            let [v1, v2, v3, v4] = self.accumulators;
+            let mut hash = v1
+                .rotate_left(1)
+                .wrapping_add(v2.rotate_left(7))
+                .wrapping_add(v3.rotate_left(12))
+                .wrapping_add(v4.rotate_left(18));
+            for accumulator in self.accumulators {
+                hash = merge_round(hash, accumulator);
+            }
+            hash
+        } else {
+            self.seed.wrapping_add(PRIME_5)
+        };
+        hash = hash.wrapping_add(self.total_len);
+
+        let mut rest = &self.buffer[..self.buffered];
+        while rest.len() >= 8 {
+            hash ^= round(0, read_u64(rest));
+            hash = hash
+                .rotate_left(27)
+                .wrapping_mul(PRIME_1)
+                .wrapping_add(PRIME_4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            hash ^= u64::from(read_u32(rest)).wrapping_mul(PRIME_1);
+            hash = hash
+                .rotate_left(23)
+                .wrapping_mul(PRIME_2)
+                .wrapping_add(PRIME_3);
+            rest = &rest[4..];
+        }
+        for byte in rest {
+            hash ^= u64::from(*byte).wrapping_mul(PRIME_5);
+            hash = hash.rotate_left(11).wrapping_mul(PRIME_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME_3);
+        hash ^ (hash >> 32)
+    }
+
+    fn process_stripe(&mut self, stripe: &[u8]) {
+        for (i, accumulator) in self.accumulators.iter_mut().enumerate() {
+            *accumulator = round(*accumulator, read_u64(&stripe[i * 8..]));
+        }
+    }
+}
+
+fn round(accumulator: u64, input: u64) -> u64 {
+    accumulator
+        .wrapping_add(input.wrapping_mul(PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME_1)
+}
+
+fn merge_round(hash: u64, accumulator: u64) -> u64 {
+    (hash ^ round(0, accumulator))
+        .wrapping_mul(PRIME_1)
+        .wrapping_add(PRIME_4)
+}
+
+fn read_u64(data: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[..4]);
+    u32::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(xxh64(b""), 0xef46_db37_51d8_e999);
+        assert_eq!(xxh64(b"a"), 0xd24e_c4f1_a98c_6e5b);
+        assert_eq!(xxh64(b"abc"), 0x44bc_2cf5_ad77_0999);
+        assert_eq!(
+            xxh64(b"Nobody inspects the spammish repetition"),
+            0xfbce_a83c_8a37_8bf1
+        );
+    }
+
+    #[test]
+    fn streaming_matches_whole_input() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        for chunk_size in [1, 5, 31, 32, 33, 100] {
+            let mut hasher = Xxh64::new(0);
+            for chunk in data.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.digest(), xxh64(&data), "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn check_appended_checksum() {
+        let mut data = br#"{"format_version":3}"#.to_vec();
+        append(&mut data);
+        assert!(data.starts_with(br#"{"format_version":3,"checksum":"xxh64:"#));
+        assert!(data.ends_with(b"\"}"));
+        assert_eq!(check(&data), Checksum::Valid);
+
+        data[2] = b'F';
+        assert_eq!(check(&data), Checksum::Mismatch);
+        assert_eq!(check(br#"{"format_version":3}"#), Checksum::Missing);
+    }
+
+    #[test]
+    fn reader_holds_back_checksum() {
+        let mut data = br#"{"deprecated_ids":{},"mappings":{}}"#.to_vec();
+        append(&mut data);
+        let mut reader = ChecksumReader::new(&data[..]);
+        let mut read = Vec::new();
+        // Small reads make sure the held back tail is handled across calls
+        let mut buf = [0; 7];
+        loop {
+            let count = reader.read(&mut buf).unwrap();
+            if count == 0 {
+                break;
+            }
+            read.extend_from_slice(&buf[..count]);
+        }
+        assert_eq!(read, data);
+        assert_eq!(reader.finish(), Checksum::Valid);
+    }
+}
@@ -0,0 +1,240 @@
+//! The mibig-taxa command line tool
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use structopt::clap::ErrorKind;
+use structopt::StructOpt;
+
+use crate::errors::PyMibigTaxonError;
+use crate::export;
+use crate::PyTaxonCache;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "mibig-taxa",
+    about = "Look up NCBI taxonomy information for MIBiG entries"
+)]
+struct Options {
+    /// Cache file to use
+    #[structopt(short, long, env = "MIBIG_TAXA_CACHE", parse(from_os_str))]
+    cache: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Build a cache file from the NCBI taxdump and MIBiG data
+    BuildCache {
+        /// rankedlineage.dmp of the NCBI taxdump, or the taxdump archive to read all dumps from
+        #[structopt(long, parse(from_os_str))]
+        taxdump: PathBuf,
+        /// merged.dmp of the NCBI taxdump
+        #[structopt(long, parse(from_os_str))]
+        merged: PathBuf,
+        /// Directory with the MIBiG JSON files
+        #[structopt(long, parse(from_os_str))]
+        datadir: PathBuf,
+        /// nodes.dmp of the NCBI taxdump, for the tree structure
+        #[structopt(long, parse(from_os_str))]
+        nodes: Option<PathBuf>,
+        /// names.dmp of the NCBI taxdump, for synonyms
+        #[structopt(long, parse(from_os_str))]
+        names: Option<PathBuf>,
+        /// division.dmp of the NCBI taxdump, for GenBank divisions
+        #[structopt(long, parse(from_os_str))]
+        division: Option<PathBuf>,
+        /// gencode.dmp of the NCBI taxdump, for genetic code names
+        #[structopt(long, parse(from_os_str))]
+        gencode: Option<PathBuf>,
+        /// Cache file to write, defaults to --cache
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Print the entries for tax IDs as TSV
+    Lookup {
+        #[structopt(required = true)]
+        tax_ids: Vec<i64>,
+        /// Resolve deprecated IDs to the ID they were merged into
+        #[structopt(long)]
+        allow_deprecated: bool,
+        /// Don't print the column names
+        #[structopt(long)]
+        no_header: bool,
+    },
+    /// Print the antiSMASH taxon for tax IDs
+    AntismashTaxon {
+        #[structopt(required = true)]
+        tax_ids: Vec<i64>,
+        /// Resolve deprecated IDs to the ID they were merged into
+        #[structopt(long)]
+        allow_deprecated: bool,
+    },
+    /// Print the tax IDs of entries with a name
+    ResolveName {
+        name: String,
+        /// Also match synonyms and common names
+        #[structopt(long)]
+        include_synonyms: bool,
+    },
+    /// Print a summary of the cache contents as JSON
+    Stats,
+}
+
+pub(crate) fn register(m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(cli_main, m)?)
+}
+
+/// Run the command line tool, returning the exit code
+///
+/// Like argparse, args are the arguments without the program name, and default to sys.argv[1:].
+#[pyfunction]
+#[pyo3(name = "main")]
+pub(crate) fn cli_main(py: Python, args: Option<Vec<String>>) -> PyResult<i32> {
+    let args: Vec<String> = match args {
+        Some(args) => std::iter::once("mibig-taxa".to_string())
+            .chain(args)
+            .collect(),
+        None => py.import("sys")?.getattr("argv")?.extract()?,
+    };
+    let options = match Options::from_iter_safe(args) {
+        Ok(options) => options,
+        Err(err) => {
+            return Ok(match err.kind {
+                ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => {
+                    // Like other output, help piped into e.g. head can be cut short
+                    let _ = writeln!(io::stdout(), "{}", err.message);
+                    0
+                }
+                _ => {
+                    eprintln!("{}", err.message);
+                    2
+                }
+            });
+        }
+    };
+
+    // Building a cache only needs the output file, everything else reads one
+    let cachefile = match (&options.command, options.cache.clone()) {
+        (
+            Command::BuildCache {
+                output: Some(output),
+                ..
+            },
+            _,
+        ) => output.clone(),
+        (_, Some(cachefile)) => cachefile,
+        (_, None) => {
+            eprintln!("mibig-taxa: no cache file given, use --cache or set MIBIG_TAXA_CACHE");
+            return Ok(2);
+        }
+    };
+
+    let output = match run(py, options.command, cachefile) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("mibig-taxa: {}", err.pvalue(py));
+            return Ok(1);
+        }
+    };
+    match io::stdout().lock().write_all(&output) {
+        // Output piped into e.g. head is closed early, that's not an error
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("mibig-taxa: {}", err);
+            Ok(1)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// Run a command, returning what to print
+fn run(py: Python, command: Command, cachefile: PathBuf) -> PyResult<Vec<u8>> {
+    let cache = PyTaxonCache::new(py, None)?;
+    let path = |path: PathBuf| path.into_py(py).into_ref(py);
+    let mut out: Vec<u8> = Vec::new();
+
+    if let Command::BuildCache {
+        taxdump,
+        merged,
+        datadir,
+        nodes,
+        names,
+        division,
+        gencode,
+        ..
+    } = command
+    {
+        cache.initialise(
+            py,
+            path(taxdump),
+            path(merged),
+            path(datadir),
+            nodes.map(path),
+            names.map(path),
+            None,
+            division.map(path),
+            gencode.map(path),
+        )?;
+        let size = cache.save(py, path(cachefile.clone()), None, None)?;
+        eprintln!("Wrote {} entries to {}", size, cachefile.display());
+        return Ok(out);
+    }
+
+    cache.load(py, path(cachefile), None, false)?;
+    let data = cache.read();
+
+    match command {
+        Command::BuildCache { .. } => unreachable!("handled above"),
+        Command::Lookup {
+            tax_ids,
+            allow_deprecated,
+            no_header,
+        } => {
+            let mut entries = Vec::with_capacity(tax_ids.len());
+            for tax_id in tax_ids {
+                let tax_id = data.find_id(tax_id, allow_deprecated)?;
+                entries.push(data.cache.entry(tax_id));
+            }
+            export::write_tsv(&entries, &data.extras, &mut out).map_err(PyMibigTaxonError::from)?;
+            if no_header {
+                let header_end = out.iter().position(|c| *c == b'\n').map_or(0, |i| i + 1);
+                out.drain(..header_end);
+            }
+        }
+        Command::AntismashTaxon {
+            tax_ids,
+            allow_deprecated,
+        } => {
+            for tax_id in tax_ids {
+                let resolved_id = data.find_id(tax_id, allow_deprecated)?;
+                let taxon = data.antismash_taxon(&data.cache.entry(resolved_id))?;
+                out.extend(format!("{}\t{}\n", tax_id, taxon).into_bytes());
+            }
+        }
+        Command::ResolveName {
+            name,
+            include_synonyms,
+        } => {
+            let tax_ids = cache.get_ids_by_name(&name, include_synonyms);
+            if tax_ids.is_empty() {
+                return Err(PyMibigTaxonError::NameNotFound(name).into());
+            }
+            for tax_id in tax_ids {
+                out.extend(format!("{}\n", tax_id).into_bytes());
+            }
+        }
+        Command::Stats => {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("indent", 2)?;
+            let stats: String = py
+                .import("json")?
+                .call_method("dumps", (cache.stats(py)?,), Some(kwargs))?
+                .extract()?;
+            out.extend(format!("{}\n", stats).into_bytes());
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,260 @@
+//! Compressed cache files and input files
+//!
+//! There are no compression libraries in the dependency tree, so this uses the python modules:
+//! zlib from the standard library for gzip, and compression.zstd (python 3.14+) or the zstandard
+//! package for zstd. Data is streamed through their compressor and decompressor objects in chunks,
+//! taking the GIL for each chunk only.
+
+use std::io::{self, Read, Write};
+
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::errors::PyMibigTaxonError;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const CHUNK_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn parse(value: &str) -> Result<Compression, PyMibigTaxonError> {
+        match value {
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(PyMibigTaxonError::InvalidOption {
+                option: "compression",
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    /// Detect the compression from the magic bytes at the start of a file
+    pub fn detect(start: &[u8]) -> Option<Compression> {
+        if start.starts_with(GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if start.starts_with(ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+/// Check that the python modules for a compression can be imported
+///
+/// The decoders and encoders only report failed imports as IO errors, so call this first while
+/// holding the GIL to raise the proper error.
+pub(crate) fn check_available(py: Python, compression: Compression) -> PyResult<()> {
+    match compression {
+        Compression::Gzip => py.import("zlib").map(|_| ()),
+        Compression::Zstd => import_zstd(py).map(|_| ()),
+    }
+}
+
+enum Zstd<'py> {
+    Stdlib(&'py PyModule),
+    Zstandard(&'py PyModule),
+}
+
+fn import_zstd(py: Python) -> PyResult<Zstd> {
+    if let Ok(zstd) = py.import("compression.zstd") {
+        return Ok(Zstd::Stdlib(zstd));
+    }
+    match py.import("zstandard") {
+        Ok(zstandard) => Ok(Zstd::Zstandard(zstandard)),
+        Err(_) => Err(PyErr::from(PyMibigTaxonError::MissingModule(
+            "zstd compression needs python 3.14 or the zstandard package",
+        ))),
+    }
+}
+
+/// Wrap a reader to decompress it if it starts with the magic bytes of a compression
+pub(crate) fn open_decompressed<'a>(reader: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+    let mut reader = io::BufReader::new(reader);
+    let start = io::BufRead::fill_buf(&mut reader)?;
+    Ok(match Compression::detect(start) {
+        Some(compression) => Box::new(decoder(reader, compression)?),
+        None => Box::new(reader),
+    })
+}
+
+/// Wrap a reader of compressed data to decompress it
+pub(crate) fn decoder<R: Read>(inner: R, compression: Compression) -> io::Result<Decoder<R>> {
+    let decompressor = Python::with_gil(|py| -> PyResult<PyObject> {
+        let decompressor = match compression {
+            // wbits of 16 + 15 selects the gzip format
+            Compression::Gzip => py
+                .import("zlib")?
+                .call_method1("decompressobj", (16 + 15,))?,
+            Compression::Zstd => match import_zstd(py)? {
+                Zstd::Stdlib(zstd) => zstd.call_method0("ZstdDecompressor")?,
+                Zstd::Zstandard(zstandard) => zstandard
+                    .call_method0("ZstdDecompressor")?
+                    .call_method0("decompressobj")?,
+            },
+        };
+        Ok(decompressor.into())
+    })
+    .map_err(to_io_error)?;
+    Ok(Decoder {
+        inner,
+        decompressor,
+        input: vec![0; CHUNK_SIZE],
+        output: Vec::new(),
+        position: 0,
+        done: false,
+    })
+}
+
+/// Wrap a writer to compress everything written to it at the given level, or the default level
+pub(crate) fn encoder<W: Write>(
+    inner: W,
+    compression: Compression,
+    level: Option<i32>,
+) -> io::Result<Encoder<W>> {
+    let compressor = Python::with_gil(|py| -> PyResult<PyObject> {
+        let compressor = match compression {
+            // Level 9 is the default of the gzip module, wbits of 16 + 15 selects the gzip format
+            Compression::Gzip => py
+                .import("zlib")?
+                .call_method1("compressobj", (level.unwrap_or(9), 8, 16 + 15))?,
+            Compression::Zstd => match import_zstd(py)? {
+                Zstd::Stdlib(zstd) => match level {
+                    Some(level) => zstd.call_method1("ZstdCompressor", (level,))?,
+                    None => zstd.call_method0("ZstdCompressor")?,
+                },
+                Zstd::Zstandard(zstandard) => match level {
+                    Some(level) => zstandard.call_method1("ZstdCompressor", (level,))?,
+                    None => zstandard.call_method0("ZstdCompressor")?,
+                }
+                .call_method0("compressobj")?,
+            },
+        };
+        Ok(compressor.into())
+    })
+    .map_err(to_io_error)?;
+    Ok(Encoder {
+        inner,
+        compressor,
+        input: Vec::with_capacity(CHUNK_SIZE),
+    })
+}
+
+/// Decompresses data with a python decompressor, reading the compressed data in chunks
+///
+/// Like the progress callbacks, this takes the GIL for each chunk, so it can be used while the GIL
+/// is released.
+pub(crate) struct Decoder<R> {
+    inner: R,
+    decompressor: PyObject,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Decompress the next chunk, returning false at the end of the data
+    fn fill(&mut self) -> io::Result<bool> {
+        let size = self.inner.read(&mut self.input)?;
+        let chunk = &self.input[..size];
+        let decompressor = &self.decompressor;
+        let output = Python::with_gil(|py| -> PyResult<Vec<u8>> {
+            let output = if size > 0 {
+                decompressor.call_method1(py, "decompress", (PyBytes::new(py, chunk),))?
+            } else if decompressor.as_ref(py).hasattr("flush")? {
+                decompressor.call_method0(py, "flush")?
+            } else {
+                // Decompressors of compression.zstd have nothing left to flush
+                return Ok(Vec::new());
+            };
+            output.extract(py)
+        })
+        .map_err(to_io_error)?;
+        self.output = output;
+        self.position = 0;
+        if size == 0 {
+            self.done = true;
+        }
+        Ok(!self.done || !self.output.is_empty())
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.done || !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let count = buf.len().min(self.output.len() - self.position);
+        buf[..count].copy_from_slice(&self.output[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// Compresses data with a python compressor in chunks, like Decoder
+pub(crate) struct Encoder<W: Write> {
+    inner: W,
+    compressor: PyObject,
+    input: Vec<u8>,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Compress the rest of the data and end the compressed stream
+    pub fn finish(mut self) -> io::Result<W> {
+        self.compress_input()?;
+        let compressor = &self.compressor;
+        let output = Python::with_gil(|py| -> PyResult<Vec<u8>> {
+            compressor.call_method0(py, "flush")?.extract(py)
+        })
+        .map_err(to_io_error)?;
+        self.inner.write_all(&output)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn compress_input(&mut self) -> io::Result<()> {
+        if self.input.is_empty() {
+            return Ok(());
+        }
+        let input = &self.input;
+        let compressor = &self.compressor;
+        let output = Python::with_gil(|py| -> PyResult<Vec<u8>> {
+            compressor
+                .call_method1(py, "compress", (PyBytes::new(py, input),))?
+                .extract(py)
+        })
+        .map_err(to_io_error)?;
+        self.input.clear();
+        self.inner.write_all(&output)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.input.len() == CHUNK_SIZE {
+            self.compress_input()?;
+        }
+        let count = buf.len().min(CHUNK_SIZE - self.input.len());
+        self.input.extend_from_slice(&buf[..count]);
+        Ok(count)
+    }
+
+    /// Only flushes the inner writer, compressors would have to end a block for more
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn to_io_error(err: PyErr) -> io::Error {
+    let message = Python::with_gil(|py| err.pvalue(py).to_string());
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,221 @@
+//! Error handling and the python exceptions raised by the module
+
+use std::error;
+use std::fmt;
+
+use mibig_taxa::MibigTaxonError;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyValueError};
+use pyo3::prelude::*;
+
+/// Like create_exception!, but the exception also derives from a builtin exception
+///
+/// Errors raised as ValueError or OSError before the module had its own exceptions stay catchable
+/// as those.
+macro_rules! create_exception_with_builtin {
+    ($module: ident, $name: ident, $base: ty, $builtin: ty) => {
+        #[repr(transparent)]
+        pub struct $name(PyAny);
+
+        pyo3::impl_exception_boilerplate!($name);
+
+        pyo3::pyobject_native_type_core!(
+            $name,
+            *$name::type_object_raw(Python::assume_gil_acquired()),
+            #module=Some(stringify!($module))
+        );
+
+        impl $name {
+            fn type_object_raw(py: Python) -> *mut pyo3::ffi::PyTypeObject {
+                use pyo3::once_cell::GILOnceCell;
+                use pyo3::types::{PyTuple, PyType};
+                use pyo3::AsPyPointer;
+                static TYPE_OBJECT: GILOnceCell<Py<PyType>> = GILOnceCell::new();
+
+                TYPE_OBJECT
+                    .get_or_init(py, || {
+                        let bases =
+                            PyTuple::new(py, [py.get_type::<$base>(), py.get_type::<$builtin>()]);
+                        let name = concat!(stringify!($module), ".", stringify!($name), "\0");
+                        // PyErr_NewException takes a tuple of bases as well as a single one
+                        unsafe {
+                            Py::from_owned_ptr(
+                                py,
+                                pyo3::ffi::PyErr_NewException(
+                                    name.as_ptr() as *mut _,
+                                    bases.as_ptr(),
+                                    std::ptr::null_mut(),
+                                ),
+                            )
+                        }
+                    })
+                    .as_ptr() as *mut _
+            }
+        }
+    };
+}
+
+create_exception!(mibig_taxa, MibigTaxaError, PyException);
+create_exception_with_builtin!(mibig_taxa, TaxonNotFoundError, MibigTaxaError, PyValueError);
+create_exception_with_builtin!(
+    mibig_taxa,
+    InvalidAntismashTaxonError,
+    MibigTaxaError,
+    PyValueError
+);
+create_exception!(mibig_taxa, AmbiguousNameError, MibigTaxaError);
+create_exception_with_builtin!(
+    mibig_taxa,
+    InvalidArgumentError,
+    MibigTaxaError,
+    PyValueError
+);
+create_exception_with_builtin!(mibig_taxa, DataFileError, MibigTaxaError, PyOSError);
+create_exception!(mibig_taxa, CacheVersionMismatch, DataFileError);
+
+#[derive(Debug)]
+pub(crate) enum PyMibigTaxonError {
+    MibigError(MibigTaxonError),
+    NotFound(i64),
+    NameNotFound(String),
+    AccessionNotFound(String),
+    AmbiguousName(String, Vec<i64>),
+    InvalidAntismashTaxon { rank: String, name: String },
+    InvalidRank(String),
+    NoLineage(i64),
+    EmptyIdList,
+    InvalidCacheFile(String),
+    InvalidExceptionFile(String),
+    CacheVersionMismatch { found: u32, supported: u32 },
+    MergeConflict(i64),
+    Frozen,
+    ReadOnly,
+    MissingModule(&'static str),
+    NotDescendant { tax_id: i64, ancestor_id: i64 },
+    InvalidOption { option: &'static str, value: String },
+    RankWithoutParent(i64),
+    ChangeInProgress,
+}
+
+impl error::Error for PyMibigTaxonError {}
+
+impl fmt::Display for PyMibigTaxonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PyMibigTaxonError::MibigError(e) => write!(f, "{}", e),
+            PyMibigTaxonError::NotFound(id) => write!(f, "ID {} not found", id),
+            PyMibigTaxonError::NameNotFound(name) => write!(f, "Name {} not found", name),
+            PyMibigTaxonError::AccessionNotFound(accession) => {
+                write!(f, "Accession {} not found", accession)
+            }
+            PyMibigTaxonError::AmbiguousName(name, ids) => {
+                write!(f, "Name {} is ambiguous, matching IDs {:?}", name, ids)
+            }
+            PyMibigTaxonError::InvalidAntismashTaxon { name, .. } => {
+                write!(f, "Can't map taxon {} to an antiSMASH taxon", name)
+            }
+            PyMibigTaxonError::InvalidRank(rank) => write!(f, "Invalid taxonomic rank {}", rank),
+            PyMibigTaxonError::NoLineage(id) => write!(f, "No lineage information for ID {}", id),
+            PyMibigTaxonError::EmptyIdList => write!(f, "Need at least one ID"),
+            PyMibigTaxonError::InvalidCacheFile(reason) => {
+                write!(f, "Invalid cache file: {}", reason)
+            }
+            PyMibigTaxonError::InvalidExceptionFile(reason) => {
+                write!(f, "Invalid antiSMASH exception file: {}", reason)
+            }
+            PyMibigTaxonError::MergeConflict(id) => {
+                write!(f, "ID {} differs between the merged caches", id)
+            }
+            PyMibigTaxonError::Frozen => write!(f, "Can't change a frozen cache"),
+            PyMibigTaxonError::ReadOnly => write!(f, "Can't change or save a read-only cache"),
+            PyMibigTaxonError::MissingModule(message) => write!(f, "{}", message),
+            PyMibigTaxonError::NotDescendant {
+                tax_id,
+                ancestor_id,
+            } => write!(f, "ID {} is not below ID {}", tax_id, ancestor_id),
+            PyMibigTaxonError::InvalidOption { option, value } => {
+                write!(f, "Invalid value {:?} for {}", value, option)
+            }
+            PyMibigTaxonError::RankWithoutParent(id) => {
+                write!(f, "Can't set a rank for ID {} without a parent ID", id)
+            }
+            PyMibigTaxonError::ChangeInProgress => write!(
+                f,
+                "Can't change the cache while this thread is already changing it"
+            ),
+            PyMibigTaxonError::CacheVersionMismatch { found, supported } => write!(
+                f,
+                "Cache file format version {} is not supported (current version is {}), please rebuild the cache",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl std::convert::From<MibigTaxonError> for PyMibigTaxonError {
+    fn from(err: MibigTaxonError) -> PyMibigTaxonError {
+        PyMibigTaxonError::MibigError(err)
+    }
+}
+
+impl std::convert::From<PyMibigTaxonError> for PyErr {
+    fn from(err: PyMibigTaxonError) -> PyErr {
+        let message = err.to_string();
+        match err {
+            PyMibigTaxonError::MibigError(MibigTaxonError::Io(_)) => PyOSError::new_err(message),
+            PyMibigTaxonError::NotFound(tax_id) => Python::with_gil(|py| {
+                let py_err = TaxonNotFoundError::new_err(message);
+                // Setting attributes on a fresh exception only fails when out of memory
+                let _ = py_err.pvalue(py).setattr("tax_id", tax_id);
+                py_err
+            }),
+            PyMibigTaxonError::NameNotFound(name) => Python::with_gil(|py| {
+                let py_err = TaxonNotFoundError::new_err(message);
+                let _ = py_err.pvalue(py).setattr("tax_id", py.None());
+                let _ = py_err.pvalue(py).setattr("name", name);
+                py_err
+            }),
+            PyMibigTaxonError::AccessionNotFound(accession) => Python::with_gil(|py| {
+                let py_err = TaxonNotFoundError::new_err(message);
+                let _ = py_err.pvalue(py).setattr("tax_id", py.None());
+                let _ = py_err.pvalue(py).setattr("accession", accession);
+                py_err
+            }),
+            PyMibigTaxonError::AmbiguousName(name, tax_ids) => Python::with_gil(|py| {
+                let py_err = AmbiguousNameError::new_err(message);
+                let _ = py_err.pvalue(py).setattr("name", name);
+                let _ = py_err.pvalue(py).setattr("tax_ids", tax_ids);
+                py_err
+            }),
+            PyMibigTaxonError::InvalidAntismashTaxon { rank, name } => Python::with_gil(|py| {
+                let py_err = InvalidAntismashTaxonError::new_err(message);
+                let _ = py_err.pvalue(py).setattr("rank", rank);
+                let _ = py_err.pvalue(py).setattr("name", name);
+                py_err
+            }),
+            PyMibigTaxonError::CacheVersionMismatch { found, supported } => {
+                Python::with_gil(|py| {
+                    let py_err = CacheVersionMismatch::new_err(message);
+                    let _ = py_err.pvalue(py).setattr("found_version", found);
+                    let _ = py_err.pvalue(py).setattr("supported_version", supported);
+                    py_err
+                })
+            }
+            PyMibigTaxonError::MibigError(_)
+            | PyMibigTaxonError::InvalidCacheFile(_)
+            | PyMibigTaxonError::InvalidExceptionFile(_) => DataFileError::new_err(message),
+            PyMibigTaxonError::InvalidRank(_) | PyMibigTaxonError::InvalidOption { .. } => {
+                InvalidArgumentError::new_err(message)
+            }
+            PyMibigTaxonError::NoLineage(_)
+            | PyMibigTaxonError::EmptyIdList
+            | PyMibigTaxonError::MergeConflict(_)
+            | PyMibigTaxonError::Frozen
+            | PyMibigTaxonError::ReadOnly
+            | PyMibigTaxonError::MissingModule(_)
+            | PyMibigTaxonError::NotDescendant { .. }
+            | PyMibigTaxonError::RankWithoutParent(_)
+            | PyMibigTaxonError::ChangeInProgress => MibigTaxaError::new_err(message),
+        }
+    }
+}
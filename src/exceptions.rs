@@ -0,0 +1,245 @@
+//! Curated exceptions to the antiSMASH taxon mapping, read from CSV, TOML or JSON files
+//!
+//! Each exception maps a tax ID or a clade name to an antiSMASH taxon. CSV files have one
+//! `taxon,category` pair per line, with an optional header line and `#` comments. TOML and JSON
+//! files hold a single table or object with the tax IDs or names as keys.
+//!
+//! There's no TOML library in the dependency tree, so TOML files are parsed with python's tomllib
+//! (python 3.11+) or the tomli package.
+
+use std::collections::{BTreeMap, HashMap};
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::errors::PyMibigTaxonError;
+
+/// (tax ID or clade name, antiSMASH taxon) pair, as given in the file
+pub(crate) type ExceptionSpec = (String, String);
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ExceptionTable {
+    by_id: HashMap<i64, String>,
+    /// Keyed by lowercase name
+    by_name: HashMap<String, String>,
+    specs: Vec<ExceptionSpec>,
+}
+
+impl ExceptionTable {
+    /// Build the table from (key, category) pairs, keys that are numbers are tax IDs
+    pub fn from_specs(specs: Vec<ExceptionSpec>) -> ExceptionTable {
+        let mut table = ExceptionTable::default();
+        for (key, category) in &specs {
+            match key.parse::<i64>() {
+                Ok(tax_id) => table.by_id.insert(tax_id, category.clone()),
+                Err(_) => table.by_name.insert(key.to_lowercase(), category.clone()),
+            };
+        }
+        table.specs = specs;
+        table
+    }
+
+    pub fn parse_csv(text: &str) -> Result<ExceptionTable, PyMibigTaxonError> {
+        let mut specs = Vec::new();
+        for (line, fields) in csv_records(text)? {
+            let fields: Vec<&str> = fields.iter().map(|field| field.trim()).collect();
+            match fields.as_slice() {
+                [""] => continue,
+                [key, category] if !key.is_empty() && !category.is_empty() => {
+                    // The header line is optional
+                    if specs.is_empty() && key.eq_ignore_ascii_case("taxon") {
+                        continue;
+                    }
+                    specs.push((key.to_string(), category.to_string()));
+                }
+                _ => {
+                    return Err(PyMibigTaxonError::InvalidExceptionFile(format!(
+                        "line {} should be taxon,category: {:?}",
+                        line, fields
+                    )))
+                }
+            }
+        }
+        Ok(ExceptionTable::from_specs(specs))
+    }
+
+    pub fn parse_json(text: &str) -> Result<ExceptionTable, PyMibigTaxonError> {
+        let exceptions: BTreeMap<String, String> = serde_json::from_str(text)
+            .map_err(|err| PyMibigTaxonError::InvalidExceptionFile(err.to_string()))?;
+        Ok(ExceptionTable::from_specs(exceptions.into_iter().collect()))
+    }
+
+    /// Parse a TOML file, keeping the exceptions in the order of the file
+    pub fn parse_toml(py: Python, text: &str) -> PyResult<ExceptionTable> {
+        let toml = py
+            .import("tomllib")
+            .or_else(|_| py.import("tomli"))
+            .map_err(|_| {
+                PyMibigTaxonError::MissingModule(
+                    "TOML exception files need python 3.11 or the tomli package",
+                )
+            })?;
+        let invalid = |reason: String| PyErr::from(PyMibigTaxonError::InvalidExceptionFile(reason));
+        let table: &PyDict = toml
+            .call_method1("loads", (text,))
+            .map_err(|err| invalid(err.pvalue(py).to_string()))?
+            .downcast()?;
+        let mut specs = Vec::with_capacity(table.len());
+        for (key, category) in table.iter() {
+            let key: String = key.extract()?;
+            let category: String = category
+                .extract()
+                .map_err(|_| invalid(format!("the category of {:?} should be a string", key)))?;
+            specs.push((key, category));
+        }
+        Ok(ExceptionTable::from_specs(specs))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.specs.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.specs.len()
+    }
+
+    /// The exceptions in the order they were given
+    pub fn specs(&self) -> Vec<ExceptionSpec> {
+        self.specs.clone()
+    }
+
+    pub fn by_id(&self, tax_id: i64) -> Option<&str> {
+        self.by_id.get(&tax_id).map(String::as_str)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&str> {
+        self.by_name.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// Split CSV text into records, with the line number each record starts on
+///
+/// Fields are parsed like python's csv module does by default: quoted fields can contain commas,
+/// line breaks and doubled quotes. Lines starting with `#` outside of quoted fields are comments.
+fn csv_records(text: &str) -> Result<Vec<(usize, Vec<String>)>, PyMibigTaxonError> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '#' if fields.is_empty() && field.trim().is_empty() => {
+                // Skip the rest of the comment line, the newline ends the empty record
+                while chars.peek().is_some_and(|next| *next != '\n') {
+                    chars.next();
+                }
+                field.clear();
+            }
+            // Whitespace in front of the opening quote is dropped, like the fields are trimmed
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                in_quotes = true;
+            }
+            ',' => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut fields)));
+                line += 1;
+                record_line = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(PyMibigTaxonError::InvalidExceptionFile(format!(
+            "the quoted field on line {} is never closed",
+            record_line
+        )));
+    }
+    if !fields.is_empty() || !field.is_empty() {
+        fields.push(field);
+        records.push((record_line, fields));
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_with_header_and_comments() {
+        let text = "taxon,category\n# Brown algae\n  Stramenopiles , plants\n\n2880,plants\r\n";
+        let table = ExceptionTable::parse_csv(text).unwrap();
+        assert_eq!(
+            table.specs(),
+            [
+                ("Stramenopiles".to_string(), "plants".to_string()),
+                ("2880".to_string(), "plants".to_string()),
+            ]
+        );
+        assert_eq!(table.by_id(2880), Some("plants"));
+        assert_eq!(table.by_name("stramenopiles"), Some("plants"));
+    }
+
+    #[test]
+    fn csv_quoted_fields() {
+        let text = "\"Bacillus, unclassified\",bacteria\n\"The \"\"odd\"\" one\", \"fungi\"\n";
+        let table = ExceptionTable::parse_csv(text).unwrap();
+        assert_eq!(table.by_name("Bacillus, unclassified"), Some("bacteria"));
+        assert_eq!(table.by_name("The \"odd\" one"), Some("fungi"));
+    }
+
+    #[test]
+    fn csv_quoted_line_break() {
+        let text = "\"two\nlines\",bacteria\nextra\n";
+        let err = ExceptionTable::parse_csv(text).unwrap_err().to_string();
+        assert!(err.contains("line 3"), "{}", err);
+    }
+
+    #[test]
+    fn csv_invalid_lines() {
+        for text in [
+            "1902\n",
+            "1902,bacteria,fungi\n",
+            ",bacteria\n",
+            "\"1902,bacteria\n",
+        ] {
+            let result = ExceptionTable::parse_csv(text);
+            assert!(
+                matches!(result, Err(PyMibigTaxonError::InvalidExceptionFile(_))),
+                "{:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn json_object() {
+        let table =
+            ExceptionTable::parse_json(r#"{"Stramenopiles": "plants", "2880": "plants"}"#).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.by_id(2880), Some("plants"));
+        assert!(ExceptionTable::parse_json(r#"{"2880": 1}"#).is_err());
+    }
+}
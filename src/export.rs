@@ -0,0 +1,180 @@
+//! Human-readable exports of the cache contents
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use serde::Serialize;
+
+use mibig_taxa::MibigTaxonError;
+
+use crate::errors::PyMibigTaxonError;
+use crate::interned::{EntryRef, EntryStore};
+use crate::storage::CacheExtras;
+
+const TSV_COLUMNS: [&str; 13] = [
+    "tax_id",
+    "name",
+    "species",
+    "genus",
+    "family",
+    "order",
+    "class",
+    "phylum",
+    "kingdom",
+    "superkingdom",
+    "rank",
+    "parent_id",
+    "manual",
+];
+
+#[derive(Serialize)]
+struct ExportEntry<'a> {
+    #[serde(flatten)]
+    entry: EntryRef<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rank: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_id: Option<i64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    manual: bool,
+}
+
+/// Get the entries to export, sorted by tax ID
+pub(crate) fn select_entries<'a>(
+    cache: &'a EntryStore,
+    ids: Option<&[i64]>,
+) -> Result<Vec<EntryRef<'a>>, PyMibigTaxonError> {
+    let mut entries = match ids {
+        Some(ids) => {
+            let mut entries = Vec::with_capacity(ids.len());
+            for tax_id in ids {
+                match cache.get(*tax_id) {
+                    Some(entry) => entries.push(entry),
+                    None => return Err(PyMibigTaxonError::NotFound(*tax_id)),
+                }
+            }
+            entries
+        }
+        None => cache.iter().collect(),
+    };
+    entries.sort_unstable_by_key(|entry| entry.tax_id);
+    entries.dedup_by_key(|entry| entry.tax_id);
+    Ok(entries)
+}
+
+pub(crate) fn write_json(
+    entries: &[EntryRef],
+    extras: &CacheExtras,
+    output: impl Write,
+) -> Result<(), MibigTaxonError> {
+    let export: Vec<ExportEntry> = entries
+        .iter()
+        .map(|entry| {
+            let node = extras.nodes.get(&entry.tax_id);
+            ExportEntry {
+                entry: *entry,
+                rank: node.map(|node| node.rank.as_str()),
+                parent_id: node.map(|node| node.parent_id),
+                manual: extras.manual.contains(&entry.tax_id),
+            }
+        })
+        .collect();
+    serde_json::to_writer_pretty(output, &export)?;
+    Ok(())
+}
+
+pub(crate) fn write_tsv(
+    entries: &[EntryRef],
+    extras: &CacheExtras,
+    mut output: impl Write,
+) -> Result<(), MibigTaxonError> {
+    writeln!(output, "{}", TSV_COLUMNS.join("\t"))?;
+    for entry in entries {
+        let node = extras.nodes.get(&entry.tax_id);
+        let rank = node.map(|node| node.rank.as_str()).unwrap_or("");
+        let parent_id = node
+            .map(|node| node.parent_id.to_string())
+            .unwrap_or_default();
+        let columns = [
+            entry.tax_id.to_string(),
+            tsv_field(entry.name),
+            tsv_field(entry.species),
+            tsv_field(entry.genus),
+            tsv_field(entry.family),
+            tsv_field(entry.order),
+            tsv_field(entry.class),
+            tsv_field(entry.phylum),
+            tsv_field(entry.kingdom),
+            tsv_field(entry.superkingdom),
+            tsv_field(rank),
+            parent_id,
+            extras.manual.contains(&entry.tax_id).to_string(),
+        ];
+        writeln!(output, "{}", columns.join("\t"))?;
+    }
+    Ok(())
+}
+
+/// Tabs and newlines would break the TSV layout
+fn tsv_field(value: &str) -> String {
+    value.replace(&['\t', '\n', '\r'][..], " ")
+}
+
+/// Build a Newick tree of the subtree below root
+///
+/// If include is set, only nodes in it are part of the tree.
+pub(crate) fn newick(
+    root: i64,
+    children: &HashMap<i64, Vec<i64>>,
+    include: Option<&HashSet<i64>>,
+    label: impl Fn(i64) -> String,
+) -> String {
+    let mut tree = String::new();
+    write_newick_node(root, children, include, &label, &mut tree);
+    tree.push(';');
+    tree
+}
+
+fn write_newick_node(
+    tax_id: i64,
+    children: &HashMap<i64, Vec<i64>>,
+    include: Option<&HashSet<i64>>,
+    label: &impl Fn(i64) -> String,
+    tree: &mut String,
+) {
+    let child_ids: Vec<i64> = children
+        .get(&tax_id)
+        .map(|child_ids| {
+            child_ids
+                .iter()
+                .copied()
+                .filter(|child_id| include.is_none_or(|include| include.contains(child_id)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if !child_ids.is_empty() {
+        tree.push('(');
+        for (i, child_id) in child_ids.into_iter().enumerate() {
+            if i > 0 {
+                tree.push(',');
+            }
+            write_newick_node(child_id, children, include, label, tree);
+        }
+        tree.push(')');
+    }
+    tree.push_str(&newick_label(&label(tax_id)));
+}
+
+/// Quote labels containing characters with a special meaning in Newick
+fn newick_label(label: &str) -> String {
+    if label.contains(
+        &[
+            ' ', '(', ')', '[', ']', ',', ':', ';', '\'', '\t', '\n', '\r',
+        ][..],
+    ) {
+        format!("'{}'", label.replace('\'', "''"))
+    } else {
+        label.to_string()
+    }
+}
@@ -0,0 +1,297 @@
+//! Entry storage with interned names
+//!
+//! Most names in the lineage of an entry are shared with many other entries, e.g. "Bacteria" is
+//! the superkingdom of most of them. Each distinct name is stored once in a string table, and
+//! entries store the indices of their names. Cache files use the same layout, see the storage
+//! module.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use mibig_taxa::{NcbiTaxEntry, TaxonCache};
+
+/// Number of names stored per entry, in NcbiTaxEntry field order
+pub(crate) const FIELD_COUNT: usize = 9;
+
+/// Indices of the names of an entry in the string table
+pub(crate) type NameIndices = [u32; FIELD_COUNT];
+
+/// Borrowed view of an entry, with the fields of NcbiTaxEntry
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub(crate) struct EntryRef<'a> {
+    pub tax_id: i64,
+    pub name: &'a str,
+    pub species: &'a str,
+    pub genus: &'a str,
+    pub family: &'a str,
+    pub order: &'a str,
+    pub class: &'a str,
+    pub phylum: &'a str,
+    pub kingdom: &'a str,
+    pub superkingdom: &'a str,
+}
+
+impl<'a> EntryRef<'a> {
+    fn from_fields(tax_id: i64, fields: [&'a str; FIELD_COUNT]) -> EntryRef<'a> {
+        let [name, species, genus, family, order, class, phylum, kingdom, superkingdom] = fields;
+        EntryRef {
+            tax_id,
+            name,
+            species,
+            genus,
+            family,
+            order,
+            class,
+            phylum,
+            kingdom,
+            superkingdom,
+        }
+    }
+
+    pub fn fields(&self) -> [&'a str; FIELD_COUNT] {
+        [
+            self.name,
+            self.species,
+            self.genus,
+            self.family,
+            self.order,
+            self.class,
+            self.phylum,
+            self.kingdom,
+            self.superkingdom,
+        ]
+    }
+}
+
+impl<'a> From<&'a NcbiTaxEntry> for EntryRef<'a> {
+    fn from(entry: &'a NcbiTaxEntry) -> EntryRef<'a> {
+        EntryRef {
+            tax_id: entry.tax_id,
+            name: &entry.name,
+            species: &entry.species,
+            genus: &entry.genus,
+            family: &entry.family,
+            order: &entry.order,
+            class: &entry.class,
+            phylum: &entry.phylum,
+            kingdom: &entry.kingdom,
+            superkingdom: &entry.superkingdom,
+        }
+    }
+}
+
+/// Each distinct name once, in the order they were first seen
+///
+/// Names are never removed, so indices stay valid. Names no entry uses any more are dropped when
+/// the store is saved, see EntryStore::to_parts().
+#[derive(Debug, Clone, Default)]
+struct StringTable {
+    strings: Vec<Arc<str>>,
+    /// Built on the first intern(), caches loaded from a file are mostly only read
+    index: Option<NameIndex>,
+}
+
+type NameIndex = HashMap<Arc<str>, u32, BuildHasherDefault<NameHasher>>;
+
+impl StringTable {
+    fn from_strings(strings: Vec<Arc<str>>) -> StringTable {
+        StringTable {
+            strings,
+            index: None,
+        }
+    }
+
+    fn intern(&mut self, value: &str) -> u32 {
+        let strings = &self.strings;
+        let index = self.index.get_or_insert_with(|| {
+            let mut index = NameIndex::default();
+            index.reserve(strings.len());
+            for (i, value) in strings.iter().enumerate() {
+                index.entry(Arc::clone(value)).or_insert(i as u32);
+            }
+            index
+        });
+        if let Some(i) = index.get(value) {
+            return *i;
+        }
+        let i = self.strings.len() as u32;
+        let value: Arc<str> = Arc::from(value);
+        self.strings.push(Arc::clone(&value));
+        index.insert(value, i);
+        i
+    }
+
+    fn get(&self, index: u32) -> &str {
+        &self.strings[index as usize]
+    }
+}
+
+/// The entries and deprecated IDs of a cache, like mibig_taxa's TaxonCache
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EntryStore {
+    pub deprecated_ids: HashMap<i64, i64>,
+    strings: StringTable,
+    entries: HashMap<i64, NameIndices>,
+}
+
+impl EntryStore {
+    /// Build a store from the entries and string table of a cache file
+    ///
+    /// Fails with the offending tax ID if an entry refers to a name not in the table.
+    pub fn from_parts(
+        deprecated_ids: HashMap<i64, i64>,
+        strings: Vec<Arc<str>>,
+        entries: HashMap<i64, NameIndices>,
+    ) -> Result<EntryStore, i64> {
+        let string_count = strings.len();
+        for (tax_id, fields) in &entries {
+            if fields.iter().any(|field| *field as usize >= string_count) {
+                return Err(*tax_id);
+            }
+        }
+        Ok(EntryStore {
+            deprecated_ids,
+            strings: StringTable::from_strings(strings),
+            entries,
+        })
+    }
+
+    /// Get the string table and entries for a cache file, leaving out unused names
+    pub fn to_parts(&self) -> (Vec<&str>, HashMap<i64, NameIndices>) {
+        let mut strings = Vec::new();
+        let mut remap = vec![None; self.strings.strings.len()];
+        let entries = self
+            .entries
+            .iter()
+            .map(|(tax_id, fields)| {
+                let mut new_fields = [0; FIELD_COUNT];
+                for (new_field, field) in new_fields.iter_mut().zip(fields) {
+                    *new_field = *remap[*field as usize].get_or_insert_with(|| {
+                        strings.push(self.strings.get(*field));
+                        strings.len() as u32 - 1
+                    });
+                }
+                (*tax_id, new_fields)
+            })
+            .collect();
+        (strings, entries)
+    }
+
+    /// Add the entries and deprecated IDs of a mibig_taxa cache, replacing existing ones
+    pub fn extend(&mut self, cache: TaxonCache) {
+        self.deprecated_ids.extend(cache.deprecated_ids);
+        self.entries.reserve(cache.mappings.len());
+        for (tax_id, entry) in cache.mappings {
+            self.insert(EntryRef {
+                tax_id,
+                ..EntryRef::from(&entry)
+            });
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains(&self, tax_id: i64) -> bool {
+        self.entries.contains_key(&tax_id)
+    }
+
+    pub fn get(&self, tax_id: i64) -> Option<EntryRef<'_>> {
+        let fields = self.entries.get(&tax_id)?;
+        Some(self.view(tax_id, fields))
+    }
+
+    /// Get an entry that is known to exist
+    ///
+    /// Panics if there is no entry for tax_id, like indexing a HashMap.
+    pub fn entry(&self, tax_id: i64) -> EntryRef<'_> {
+        match self.get(tax_id) {
+            Some(entry) => entry,
+            None => panic!("no entry for tax ID {}", tax_id),
+        }
+    }
+
+    /// Iterate over the entries, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = EntryRef<'_>> {
+        self.entries
+            .iter()
+            .map(move |(tax_id, fields)| self.view(*tax_id, fields))
+    }
+
+    pub fn tax_ids(&self) -> impl Iterator<Item = i64> + '_ {
+        self.entries.keys().copied()
+    }
+
+    /// Add an entry, replacing any existing entry with the same tax ID
+    pub fn insert(&mut self, entry: EntryRef) {
+        let mut fields = [0; FIELD_COUNT];
+        for (field, value) in fields.iter_mut().zip(entry.fields()) {
+            *field = self.strings.intern(value);
+        }
+        self.entries.insert(entry.tax_id, fields);
+    }
+
+    pub fn remove(&mut self, tax_id: i64) {
+        self.entries.remove(&tax_id);
+    }
+
+    /// Change the name of an entry, returns false if there is no entry for tax_id
+    pub fn set_name(&mut self, tax_id: i64, name: &str) -> bool {
+        if !self.entries.contains_key(&tax_id) {
+            return false;
+        }
+        let index = self.strings.intern(name);
+        if let Some(fields) = self.entries.get_mut(&tax_id) {
+            fields[0] = index;
+        }
+        true
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(i64) -> bool) {
+        self.entries.retain(|tax_id, _| keep(*tax_id));
+    }
+
+    fn view(&self, tax_id: i64, fields: &NameIndices) -> EntryRef<'_> {
+        EntryRef::from_fields(tax_id, fields.map(|field| self.strings.get(field)))
+    }
+}
+
+/// FxHash-style hasher for the name index, much faster than SipHash for short strings
+///
+/// The names come from NCBI dumps, so there's no need to guard against crafted collisions.
+#[derive(Debug, Clone, Copy, Default)]
+struct NameHasher(u64);
+
+impl NameHasher {
+    fn add(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+impl Hasher for NameHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.add(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        let rest = words.remainder();
+        if !rest.is_empty() {
+            let mut word = [0; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            self.add(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, byte: u8) {
+        self.add(u64::from(byte));
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,218 @@
+//! Exports in the formats other python taxonomy libraries read
+//!
+//! taxopy reads the NCBI dump files, a subset of which the taxdump exports write from the cache.
+//! ete3 keeps its NCBI taxonomy in an SQLite database. There's no SQLite library in the dependency
+//! tree, so the database is written with python's sqlite3 module.
+
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+
+use mibig_taxa::MibigTaxonError;
+
+use crate::errors::PyMibigTaxonError;
+use crate::interned::EntryStore;
+use crate::storage::CacheExtras;
+use crate::taxdump;
+
+/// Version of the database layout of ete3's NCBITaxa, it rebuilds databases of other versions
+const ETE3_DB_VERSION: i64 = 2;
+
+/// The tables and indexes ete3 creates, see ete3.ncbi_taxonomy.ncbiquery
+const ETE3_SCHEMA: &str = "
+CREATE TABLE stats (version INT PRIMARY KEY);
+CREATE TABLE species (taxid INT PRIMARY KEY, parent INT, spname VARCHAR(50) COLLATE NOCASE,
+    common VARCHAR(50) COLLATE NOCASE, rank VARCHAR(50), track TEXT);
+CREATE TABLE synonym (taxid INT, spname VARCHAR(50) COLLATE NOCASE, PRIMARY KEY (spname, taxid));
+CREATE TABLE merged (taxid_old INT, taxid_new INT);
+CREATE INDEX spname1 ON species (spname COLLATE NOCASE);
+CREATE INDEX spname2 ON synonym (spname COLLATE NOCASE);
+";
+
+/// Name classes ete3 stores in its synonym table, out of the ones kept by the cache
+const ETE3_SYNONYM_CLASSES: [&str; 2] = ["synonym", "equivalent name"];
+
+/// Name class ete3 stores as the common name
+const ETE3_COMMON_CLASS: &str = "genbank common name";
+
+/// Rows passed to sqlite3 at once, to keep the python objects for them small
+const ETE3_BATCH_SIZE: usize = 100_000;
+
+/// Get the tax IDs of all nodes, sorted
+fn sorted_node_ids(extras: &CacheExtras) -> Result<Vec<i64>, PyMibigTaxonError> {
+    if extras.nodes.is_empty() {
+        return Err(PyMibigTaxonError::NoLineage(1));
+    }
+    let mut tax_ids: Vec<i64> = extras.nodes.keys().copied().collect();
+    tax_ids.sort_unstable();
+    Ok(tax_ids)
+}
+
+/// Line of an NCBI dump file, the fields are separated by `\t|\t` and the line ends with `\t|`
+fn write_dump_line(output: &mut impl Write, fields: &[&str]) -> Result<(), MibigTaxonError> {
+    writeln!(output, "{}\t|", fields.join("\t|\t"))?;
+    Ok(())
+}
+
+/// Write nodes.dmp, names.dmp and merged.dmp to dir, returning the number of nodes
+///
+/// Columns the cache doesn't store are left empty.
+pub(crate) fn write_taxdump(
+    cache: &EntryStore,
+    extras: &CacheExtras,
+    dir: &Path,
+) -> Result<usize, PyMibigTaxonError> {
+    let tax_ids = sorted_node_ids(extras)?;
+    fs::create_dir_all(dir).map_err(MibigTaxonError::from)?;
+    let create = |name: &str| -> Result<BufWriter<fs::File>, MibigTaxonError> {
+        Ok(BufWriter::new(fs::File::create(dir.join(name))?))
+    };
+
+    let mut nodes = create("nodes.dmp")?;
+    let mut names = create("names.dmp")?;
+    for tax_id in &tax_ids {
+        let node = &extras.nodes[tax_id];
+        let id_column = |id: Option<i64>| id.map(|id| id.to_string()).unwrap_or_default();
+        write_dump_line(
+            &mut nodes,
+            &[
+                &tax_id.to_string(),
+                &node.parent_id.to_string(),
+                &node.rank,
+                "",
+                &id_column(node.division_id),
+                "",
+                &id_column(node.genetic_code_id),
+                "",
+                &id_column(node.mito_code_id),
+                "",
+                "",
+                "",
+                "",
+            ],
+        )?;
+
+        let tax_id_column = tax_id.to_string();
+        let name = cache.get(*tax_id).map(|entry| entry.name).unwrap_or("");
+        write_dump_line(&mut names, &[&tax_id_column, name, "", "scientific name"])?;
+        for other_name in extras.names.get(tax_id).into_iter().flatten() {
+            write_dump_line(
+                &mut names,
+                &[&tax_id_column, &other_name.name, "", &other_name.name_class],
+            )?;
+        }
+    }
+    nodes.flush().map_err(MibigTaxonError::from)?;
+    names.flush().map_err(MibigTaxonError::from)?;
+
+    let mut merged_ids: Vec<(&i64, &i64)> = cache.deprecated_ids.iter().collect();
+    merged_ids.sort_unstable();
+    let mut merged = create("merged.dmp")?;
+    for (old_id, new_id) in merged_ids {
+        write_dump_line(&mut merged, &[&old_id.to_string(), &new_id.to_string()])?;
+    }
+    merged.flush().map_err(MibigTaxonError::from)?;
+
+    Ok(tax_ids.len())
+}
+
+/// Write an SQLite database in the layout of ete3's NCBITaxa to path, returning the number of nodes
+///
+/// An existing file at path is replaced.
+pub(crate) fn write_ete3_sqlite(
+    py: Python,
+    cache: &EntryStore,
+    extras: &CacheExtras,
+    path: &Path,
+) -> PyResult<usize> {
+    let tax_ids = sorted_node_ids(extras)?;
+    let sqlite3 = py.import("sqlite3").map_err(|_| {
+        PyMibigTaxonError::MissingModule("ete3 exports need python's sqlite3 module")
+    })?;
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            return Err(PyMibigTaxonError::from(MibigTaxonError::from(err)).into())
+        }
+        _ => {}
+    }
+
+    let db = sqlite3.call_method1("connect", (path,))?;
+    db.call_method1("executescript", (ETE3_SCHEMA,))?;
+    db.call_method1(
+        "execute",
+        ("INSERT INTO stats (version) VALUES (?)", (ETE3_DB_VERSION,)),
+    )?;
+
+    for batch in tax_ids.chunks(ETE3_BATCH_SIZE) {
+        let mut species = Vec::with_capacity(batch.len());
+        let mut synonyms = Vec::new();
+        for tax_id in batch {
+            let node = &extras.nodes[tax_id];
+            let other_names = extras.names.get(tax_id).map(Vec::as_slice).unwrap_or(&[]);
+            let common = other_names
+                .iter()
+                .find(|name| name.name_class == ETE3_COMMON_CLASS)
+                .map(|name| name.name.as_str())
+                .unwrap_or("");
+            let track: Vec<String> = taxdump::get_lineage(*tax_id, &extras.nodes)
+                .iter()
+                .map(i64::to_string)
+                .collect();
+            // ete3 leaves the parent of the root node empty
+            let parent_id = Some(node.parent_id).filter(|parent_id| parent_id != tax_id);
+            species.push((
+                *tax_id,
+                parent_id,
+                cache.get(*tax_id).map(|entry| entry.name).unwrap_or(""),
+                common,
+                node.rank.as_str(),
+                track.join(","),
+            ));
+
+            let mut names: Vec<&str> = other_names
+                .iter()
+                .filter(|name| ETE3_SYNONYM_CLASSES.contains(&name.name_class.as_str()))
+                .map(|name| name.name.as_str())
+                .collect();
+            // Names only differing in case would break the case-insensitive primary key
+            names.sort_unstable_by_key(|name| name.to_lowercase());
+            names.dedup_by_key(|name| name.to_lowercase());
+            synonyms.extend(names.into_iter().map(|name| (*tax_id, name)));
+        }
+        db.call_method1(
+            "executemany",
+            (
+                "INSERT INTO species (taxid, parent, spname, common, rank, track) VALUES (?, ?, ?, ?, ?, ?)",
+                PyList::new(py, species),
+            ),
+        )?;
+        db.call_method1(
+            "executemany",
+            (
+                "INSERT INTO synonym (taxid, spname) VALUES (?, ?)",
+                PyList::new(py, synonyms),
+            ),
+        )?;
+    }
+
+    let mut merged_ids: Vec<(i64, i64)> = cache
+        .deprecated_ids
+        .iter()
+        .map(|(old_id, new_id)| (*old_id, *new_id))
+        .collect();
+    merged_ids.sort_unstable();
+    db.call_method1(
+        "executemany",
+        (
+            "INSERT INTO merged (taxid_old, taxid_new) VALUES (?, ?)",
+            PyList::new(py, merged_ids),
+        ),
+    )?;
+    db.call_method0("commit")?;
+    db.call_method0("close")?;
+
+    Ok(tax_ids.len())
+}
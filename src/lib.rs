@@ -1,50 +1,61 @@
-use std::error;
-use std::fmt;
+// pyo3's generated wrappers for optional arguments trip this lint
+#![allow(clippy::needless_option_as_deref)]
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Instant;
 
 use mibig_taxa::NcbiTaxEntry;
-use pyo3::exceptions::{PyOSError, PyValueError};
+use pyo3::exceptions::{PyKeyError, PyTypeError};
 use pyo3::prelude::*;
-use pyo3::types::{PyLong, PyUnicode};
+use pyo3::types::{PyBytes, PyDict, PyString, PyTuple};
 
 use mibig_taxa::{MibigTaxonError, TaxonCache};
 
-#[derive(Debug)]
-enum PyMibigTaxonError {
-    MibigError(MibigTaxonError),
-    NotFound(i64),
-    InvalidAntismashTaxon(String),
-}
-
-impl error::Error for PyMibigTaxonError {}
-
-impl fmt::Display for PyMibigTaxonError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            PyMibigTaxonError::MibigError(e) => write!(f, "{}", e),
-            PyMibigTaxonError::NotFound(id) => write!(f, "ID {} not found", id),
-            PyMibigTaxonError::InvalidAntismashTaxon(tax) => {
-                write!(f, "Can't map taxon {} to an antiSMASH taxon", tax)
-            }
-        }
-    }
-}
-
-impl std::convert::From<MibigTaxonError> for PyMibigTaxonError {
-    fn from(err: MibigTaxonError) -> PyMibigTaxonError {
-        PyMibigTaxonError::MibigError(err)
-    }
-}
+mod accession;
+mod aliases;
+mod archive;
+mod checksum;
+mod cli;
+mod compression;
+mod errors;
+mod exceptions;
+mod export;
+mod interned;
+mod interop;
+mod lineage;
+mod logging;
+mod mapped;
+mod merge;
+mod missing;
+mod progress;
+mod search;
+mod storage;
+mod taxdump;
+mod validate;
+mod vectorized;
+mod writer;
 
-impl std::convert::From<PyMibigTaxonError> for PyErr {
-    fn from(err: PyMibigTaxonError) -> PyErr {
-        match err {
-            PyMibigTaxonError::MibigError(_) => PyOSError::new_err(err.to_string()),
-            PyMibigTaxonError::NotFound(_) | PyMibigTaxonError::InvalidAntismashTaxon(_) => {
-                PyValueError::new_err(err.to_string())
-            }
-        }
-    }
-}
+use crate::accession::AccessionIndex;
+use crate::aliases::{Aliases, NameAliasSpec};
+use crate::compression::Compression;
+use crate::errors::{
+    AmbiguousNameError, CacheVersionMismatch, DataFileError, InvalidAntismashTaxonError,
+    InvalidArgumentError, MibigTaxaError, PyMibigTaxonError, TaxonNotFoundError,
+};
+use crate::exceptions::{ExceptionSpec, ExceptionTable};
+use crate::interned::{EntryRef, EntryStore};
+use crate::lineage::LineageStyle;
+use crate::mapped::MappedCache;
+use crate::merge::MergePolicy;
+use crate::missing::OnMissing;
+use crate::progress::Progress;
+use crate::storage::CacheExtras;
+use crate::taxdump::{BuildMetadata, TaxdumpPaths, TaxonNode};
+use crate::writer::{WriteGuard, WriteLock};
 
 /// Python version of NcbiTaxEntry
 #[pyclass(name = "TaxonEntry", module = "mibig_taxa")]
@@ -69,6 +80,12 @@ struct PyTaxonEntry {
     kingdom: String,
     #[pyo3(get)]
     superkingdom: String,
+    #[pyo3(get)]
+    synonyms: Vec<String>,
+    #[pyo3(get)]
+    rank: Option<String>,
+    #[pyo3(get)]
+    parent_id: Option<i64>,
 }
 
 #[pymethods]
@@ -82,13 +99,12 @@ impl PyTaxonEntry {
     }
 
     pub fn get_antismash_taxon(&self) -> PyResult<String> {
-        let ncbi_entry: NcbiTaxEntry = self.into();
-        get_taxon_from_entry(&ncbi_entry)
+        get_taxon_from_entry(&EntryRef::from(self))
     }
 }
 
-impl std::convert::From<&NcbiTaxEntry> for PyTaxonEntry {
-    fn from(entry: &NcbiTaxEntry) -> Self {
+impl std::convert::From<EntryRef<'_>> for PyTaxonEntry {
+    fn from(entry: EntryRef) -> Self {
         PyTaxonEntry {
             tax_id: entry.tax_id,
             name: entry.name.to_string(),
@@ -100,176 +116,2770 @@ impl std::convert::From<&NcbiTaxEntry> for PyTaxonEntry {
             phylum: entry.phylum.to_string(),
             kingdom: entry.kingdom.to_string(),
             superkingdom: entry.superkingdom.to_string(),
+            synonyms: Vec::new(),
+            rank: None,
+            parent_id: None,
         }
     }
 }
 
-impl std::convert::From<&PyTaxonEntry> for NcbiTaxEntry {
-    fn from(entry: &PyTaxonEntry) -> Self {
-        NcbiTaxEntry {
+impl<'a> std::convert::From<&'a PyTaxonEntry> for EntryRef<'a> {
+    fn from(entry: &'a PyTaxonEntry) -> Self {
+        EntryRef {
             tax_id: entry.tax_id,
-            name: entry.name.to_string(),
-            species: entry.species.to_string(),
-            genus: entry.genus.to_string(),
-            family: entry.family.to_string(),
-            order: entry.order.to_string(),
-            class: entry.class.to_string(),
-            phylum: entry.phylum.to_string(),
-            kingdom: entry.kingdom.to_string(),
-            superkingdom: entry.superkingdom.to_string(),
+            name: &entry.name,
+            species: &entry.species,
+            genus: &entry.genus,
+            family: &entry.family,
+            order: &entry.order,
+            class: &entry.class,
+            phylum: &entry.phylum,
+            kingdom: &entry.kingdom,
+            superkingdom: &entry.superkingdom,
         }
     }
 }
 
 /// Python version of the TaxonCache
+///
+/// The contents are shared copy-on-write: lookups work on a snapshot taken under a short read
+/// lock, and changes build new contents that replace the old ones. This keeps lookups from other
+/// threads safe while a long-running load() or initialise() has released the GIL. Changes are
+/// serialised with the write lock, see the writer module.
 #[pyclass(name = "TaxonCache", module = "mibig_taxa")]
 struct PyTaxonCache {
-    cache: TaxonCache,
+    data: RwLock<Arc<CacheData>>,
+    writer: WriteLock,
+    frozen: bool,
+    /// Opened with open_readonly(), can't be saved either
+    readonly: bool,
+}
+
+// Lookups from other threads share the contents through the RwLock, which relies on these. They
+// would also be what a free-threaded python build needs, see the README.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<CacheData>();
+    assert_send_sync::<PyTaxonCache>();
+};
+
+/// Cache contents and the lookup tables derived from them
+#[derive(Clone)]
+struct CacheData {
+    cache: EntryStore,
+    extras: CacheExtras,
+    children: HashMap<i64, Vec<i64>>,
+    name_index: HashMap<String, Vec<i64>>,
+    synonym_index: HashMap<String, Vec<i64>>,
+    rules: Vec<MappingRule>,
+    exceptions: ExceptionTable,
+    aliases: Aliases,
+    /// Shared between copies, as it can be much larger than the cache itself
+    accessions: Arc<AccessionIndex>,
+    /// Size of the file the cache was loaded from
+    file_size: Option<u64>,
+}
+
+/// (rank, name, target) triple used to set mapping rules from python
+type MappingRuleSpec = (String, String, String);
+
+/// Rank and name aliases added from python
+type CustomAliases = (Vec<(String, String)>, Vec<NameAliasSpec>);
+
+/// Pickled cache contents, mapping rules, frozen flag, aliases, antiSMASH exceptions and
+/// read-only flag
+type PickleState = (
+    Py<PyBytes>,
+    Vec<MappingRuleSpec>,
+    bool,
+    CustomAliases,
+    Vec<ExceptionSpec>,
+    bool,
+);
+
+/// User-defined antiSMASH taxon mapping, checked before the built-in mapping
+#[derive(Debug, Clone)]
+struct MappingRule {
+    rank: String,
+    name: String,
+    target: String,
 }
 
 #[pymethods]
 impl PyTaxonCache {
     #[new]
-    fn new(cachefile: Option<&PyUnicode>) -> PyResult<Self> {
-        let mut cache = PyTaxonCache {
-            cache: TaxonCache::new(),
-        };
+    fn new(py: Python, cachefile: Option<&PyAny>) -> PyResult<Self> {
+        let cache = PyTaxonCache::from_data(Arc::new(CacheData::default()), false);
 
         if let Some(filename) = cachefile {
-            cache.load(filename)?;
+            cache.load(py, filename, None, false)?;
         }
         Ok(cache)
     }
 
+    #[args(
+        nodes_dump = "None",
+        names_dump = "None",
+        progress_callback = "None",
+        division_dump = "None",
+        gencode_dump = "None"
+    )]
+    #[allow(clippy::too_many_arguments)]
     pub fn initialise(
-        &mut self,
-        taxdump: &PyUnicode,
-        merged_id_dump: &PyUnicode,
-        datadir: &PyUnicode,
+        &self,
+        py: Python,
+        taxdump: &PyAny,
+        merged_id_dump: &PyAny,
+        datadir: &PyAny,
+        nodes_dump: Option<&PyAny>,
+        names_dump: Option<&PyAny>,
+        progress_callback: Option<PyObject>,
+        division_dump: Option<&PyAny>,
+        gencode_dump: Option<&PyAny>,
     ) -> PyResult<()> {
-        self.cache
-            .initialise_from_paths(
-                taxdump.extract()?,
-                merged_id_dump.extract()?,
-                datadir.extract()?,
-            )
-            .map_err(PyMibigTaxonError::from)?;
+        self.check_frozen()?;
+        let paths = TaxdumpPaths {
+            taxdump: extract_path(taxdump)?,
+            merged_id_dump: extract_path(merged_id_dump)?,
+            datadir: extract_path(datadir)?,
+            nodes_dump: nodes_dump.map(extract_path).transpose()?,
+            names_dump: names_dump.map(extract_path).transpose()?,
+            division_dump: division_dump.map(extract_path).transpose()?,
+            gencode_dump: gencode_dump.map(extract_path).transpose()?,
+        };
+        let progress = Progress::new(progress_callback);
+        let start = Instant::now();
+
+        // Building the cache takes a while, don't block other python threads
+        let _guard = self.lock_writer()?;
+        let mut data = CacheData::clone(&self.read());
+        let cache = &mut data.cache;
+        let progress_ref = &progress;
+        let result = py.allow_threads(move || taxdump::initialise(cache, paths, progress_ref));
+        if let Some(err) = progress.take_error() {
+            return Err(err);
+        }
+        let extras = result.map_err(PyMibigTaxonError::from)?;
+
+        data.extras.nodes.extend(extras.nodes);
+        data.extras.names.extend(extras.names);
+        data.extras.divisions.extend(extras.divisions);
+        data.extras.genetic_codes.extend(extras.genetic_codes);
+        data.extras.taxdump_date = extras.taxdump_date;
+        data.extras.metadata = extras.metadata;
+        data.rebuild_indexes();
+        logging::info(&format!(
+            "Initialised the cache with {} entries in {:.2}s",
+            data.cache.len(),
+            start.elapsed().as_secs_f64()
+        ));
+        self.replace(data);
         Ok(())
     }
 
-    pub fn load(&mut self, cachefile: &PyUnicode) -> PyResult<usize> {
-        let size = self
-            .cache
-            .load_path(&cachefile.extract()?)
+    /// Awaitable version of initialise(), running in the default executor of the event loop
+    #[args(args = "*", kwargs = "**")]
+    pub fn initialise_async(
+        slf: PyRef<Self>,
+        py: Python,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+        run_in_executor(py, slf.into_py(py), "initialise", args, kwargs)
+    }
+
+    /// Load a cache file, replacing the current contents
+    ///
+    /// With streaming set, the file is parsed while reading instead of being read into memory
+    /// first. This is slower, but keeps the peak memory use close to the size of the cache.
+    #[args(progress_callback = "None", streaming = "false")]
+    pub fn load(
+        &self,
+        py: Python,
+        cachefile: &PyAny,
+        progress_callback: Option<PyObject>,
+        streaming: bool,
+    ) -> PyResult<usize> {
+        self.check_frozen()?;
+        let cachefile = extract_path(cachefile)?;
+        let file_path = cachefile.clone();
+        let progress = Progress::new(progress_callback);
+        let start = Instant::now();
+
+        let _guard = self.lock_writer()?;
+        let progress_ref = &progress;
+        let compression = storage::detect_compression(&cachefile).map_err(MibigTaxonError::from);
+        let compression = compression.map_err(PyMibigTaxonError::from)?;
+        if let Some(compression) = compression {
+            compression::check_available(py, compression)?;
+        }
+        let result = py.allow_threads(move || {
+            let open = || storage::open_path(&cachefile, compression, progress_ref, "load");
+            if streaming {
+                storage::load_streaming(open)
+            } else {
+                storage::load(open().map_err(MibigTaxonError::from)?)
+            }
+        });
+        if let Some(err) = progress.take_error() {
+            return Err(err);
+        }
+        let (cache, extras) = result?;
+
+        // Settings and the accession index don't come from the cache file
+        let old_data = self.read();
+        let mut data = CacheData {
+            cache,
+            extras,
+            rules: old_data.rules.clone(),
+            exceptions: old_data.exceptions.clone(),
+            aliases: old_data.aliases.clone(),
+            accessions: Arc::clone(&old_data.accessions),
+            file_size: fs::metadata(&file_path).ok().map(|metadata| metadata.len()),
+            ..CacheData::default()
+        };
+        data.rebuild_indexes();
+        let size = data.cache.len();
+        logging::info(&format!(
+            "Loaded {} entries and {} merged IDs from {} in {:.2}s",
+            size,
+            data.cache.deprecated_ids.len(),
+            file_path.display(),
+            start.elapsed().as_secs_f64()
+        ));
+        self.replace(data);
+        Ok(size)
+    }
+
+    /// Check the checksum of a cache file written by save(), without loading it
+    ///
+    /// Raises a DataFileError if the file is truncated, corrupted or has no checksum because it
+    /// was written by an older version. load() checks the checksum too, but accepts files without
+    /// one.
+    #[staticmethod]
+    #[args(progress_callback = "None")]
+    pub fn verify(
+        py: Python,
+        cachefile: &PyAny,
+        progress_callback: Option<PyObject>,
+    ) -> PyResult<()> {
+        let cachefile = extract_path(cachefile)?;
+        let progress = Progress::new(progress_callback);
+
+        let progress_ref = &progress;
+        let compression = storage::detect_compression(&cachefile).map_err(MibigTaxonError::from);
+        let compression = compression.map_err(PyMibigTaxonError::from)?;
+        if let Some(compression) = compression {
+            compression::check_available(py, compression)?;
+        }
+        let result = py.allow_threads(move || {
+            let input = storage::open_path(&cachefile, compression, progress_ref, "verify");
+            storage::verify(input.map_err(MibigTaxonError::from)?)
+        });
+        if let Some(err) = progress.take_error() {
+            return Err(err);
+        }
+        Ok(result?)
+    }
+
+    /// Awaitable version of load(), running in the default executor of the event loop
+    #[args(args = "*", kwargs = "**")]
+    pub fn load_async(
+        slf: PyRef<Self>,
+        py: Python,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+        run_in_executor(py, slf.into_py(py), "load", args, kwargs)
+    }
+
+    /// Save the cache, optionally compressed with "gzip" or "zstd"
+    ///
+    /// level is the compression level, defaulting to the default level of the compression.
+    /// load() detects compressed files automatically.
+    #[args(compression = "None", level = "None")]
+    pub fn save(
+        &self,
+        py: Python,
+        cachefile: &PyAny,
+        compression: Option<&str>,
+        level: Option<i32>,
+    ) -> PyResult<usize> {
+        self.check_writable()?;
+        let data = self.read();
+        let cachefile = extract_path(cachefile)?;
+        let compression = compression.map(Compression::parse).transpose()?;
+
+        if let Some(compression) = compression {
+            compression::check_available(py, compression)?;
+        }
+
+        let cache = &data.cache;
+        let extras = &data.extras;
+        let compression = compression.map(|compression| (compression, level));
+        let size = py
+            .allow_threads(move || storage::save_path(cache, extras, &cachefile, compression))
             .map_err(PyMibigTaxonError::from)?;
         Ok(size)
     }
 
-    pub fn save(&self, cachefile: &PyUnicode) -> PyResult<usize> {
-        let size = self
-            .cache
-            .save_path(&cachefile.extract()?)
+    /// Remove all entries not needed by the MIBiG entries in datadir
+    ///
+    /// See prune_to_ids() for what is kept.
+    pub fn prune_to_datadir(&self, py: Python, datadir: &PyAny) -> PyResult<usize> {
+        self.check_frozen()?;
+        let datadir = extract_path(datadir)?;
+
+        let tax_ids = py
+            .allow_threads(move || TaxonCache::new().find_taxids(datadir))
             .map_err(PyMibigTaxonError::from)?;
+        self.update(|data| Ok(data.prune(tax_ids)))
+    }
+
+    /// Remove all entries except the ones in ids and their ancestors
+    ///
+    /// Deprecated IDs are resolved, and only deprecated IDs pointing to kept entries are kept.
+    /// Unknown IDs are ignored. Returns the number of entries left in the cache.
+    pub fn prune_to_ids(&self, ids: Vec<i64>) -> PyResult<usize> {
+        self.update(|data| Ok(data.prune(ids.into_iter().collect())))
+    }
+
+    /// Add the entries and deprecated IDs of another cache
+    ///
+    /// prefer decides which data is kept for IDs the caches disagree on: "newer" treats other as
+    /// the newer snapshot and takes its data, "older" keeps the existing data and "error" refuses
+    /// to merge. Returns the number of entries in the merged cache.
+    #[args(prefer = "\"newer\"")]
+    pub fn merge(&self, other: PyRef<PyTaxonCache>, prefer: &str) -> PyResult<usize> {
+        let policy = MergePolicy::parse(prefer)?;
+        let other = other.read();
+        self.update(|data| {
+            merge::merge(
+                &mut data.cache,
+                &mut data.extras,
+                &other.cache,
+                &other.extras,
+                policy,
+            )?;
+            data.rebuild_indexes();
+            Ok(data.cache.len())
+        })
+    }
+
+    /// Add an entry by hand, replacing any existing entry with the same ID
+    ///
+    /// Ranks not given are set to "Unknown", like for taxa without that rank in the taxdump. rank
+    /// and parent_id add the entry to the tree, a rank can only be set together with a parent.
+    /// Manual entries are kept by save() and load() and marked as manual in exports.
+    #[args(
+        rank = "None",
+        parent_id = "None",
+        class_ = "None",
+        order = "None",
+        family = "None",
+        genus = "None",
+        species = "None"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_entry(
+        &self,
+        tax_id: i64,
+        name: String,
+        superkingdom: String,
+        kingdom: String,
+        phylum: String,
+        rank: Option<String>,
+        parent_id: Option<i64>,
+        class_: Option<String>,
+        order: Option<String>,
+        family: Option<String>,
+        genus: Option<String>,
+        species: Option<String>,
+    ) -> PyResult<()> {
+        if parent_id.is_none() && rank.is_some() {
+            return Err(PyMibigTaxonError::RankWithoutParent(tax_id).into());
+        }
+        let unknown = || "Unknown".to_string();
+        let entry = NcbiTaxEntry {
+            tax_id,
+            name,
+            species: species.unwrap_or_else(unknown),
+            genus: genus.unwrap_or_else(unknown),
+            family: family.unwrap_or_else(unknown),
+            order: order.unwrap_or_else(unknown),
+            class: class_.unwrap_or_else(unknown),
+            phylum,
+            kingdom,
+            superkingdom,
+        };
+        self.update(|data| {
+            data.cache.deprecated_ids.remove(&tax_id);
+            data.cache.insert(EntryRef::from(&entry));
+            if let Some(parent_id) = parent_id {
+                let rank = rank.unwrap_or_else(|| "no rank".to_string());
+                data.extras.nodes.insert(
+                    tax_id,
+                    TaxonNode {
+                        parent_id,
+                        rank,
+                        ..Default::default()
+                    },
+                );
+            }
+            data.extras.manual.insert(tax_id);
+            data.rebuild_indexes();
+            Ok(())
+        })
+    }
+
+    /// Change the name of an existing entry by hand
+    ///
+    /// The entry is marked as manual, see add_entry().
+    pub fn override_name(&self, tax_id: i64, name: String) -> PyResult<()> {
+        self.update(|data| {
+            if !data.cache.set_name(tax_id, &name) {
+                return Err(PyMibigTaxonError::NotFound(tax_id));
+            }
+            data.extras.manual.insert(tax_id);
+            data.rebuild_indexes();
+            Ok(())
+        })
+    }
+
+    /// Whether an entry was added or changed by hand
+    pub fn is_manual(&self, tax_id: i64) -> bool {
+        self.read().extras.manual.contains(&tax_id)
+    }
+
+    /// Get a summary of the cache contents as a dict
+    ///
+    /// Contains the number of entries and deprecated IDs, entry counts per superkingdom and per
+    /// antiSMASH taxon ("unmapped" for entries without one), the modification date of the taxdump
+    /// used to build the cache and the size of the file it was loaded from, if known.
+    pub fn stats(&self, py: Python) -> PyResult<PyObject> {
+        let data = self.read();
+
+        let mut superkingdoms: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut antismash_taxa: BTreeMap<String, usize> = BTreeMap::new();
+        for entry in data.cache.iter() {
+            *superkingdoms.entry(entry.superkingdom).or_default() += 1;
+            let taxon = data
+                .antismash_taxon(&entry)
+                .unwrap_or_else(|_| "unmapped".to_string());
+            *antismash_taxa.entry(taxon).or_default() += 1;
+        }
+
+        let stats = PyDict::new(py);
+        stats.set_item("entries", data.cache.len())?;
+        stats.set_item("deprecated_ids", data.cache.deprecated_ids.len())?;
+        stats.set_item("superkingdoms", superkingdoms)?;
+        stats.set_item("antismash_taxa", antismash_taxa)?;
+        stats.set_item("taxdump_date", &data.extras.taxdump_date)?;
+        stats.set_item("file_size", data.file_size)?;
+        Ok(stats.into())
+    }
+
+    /// Get where and when the cache was built as a dict, for reproducibility audits
+    ///
+    /// Contains the modification date of the taxdump, the absolute paths of the taxdump and the
+    /// MIBiG data directory given to initialise(), the checksum of merged.dmp, the build time and
+    /// the version of this library. Values are None for caches built with older versions.
+    pub fn metadata(&self, py: Python) -> PyResult<PyObject> {
+        let data = self.read();
+        let metadata = data.extras.metadata.as_ref();
+        let field = |get: fn(&BuildMetadata) -> &str| metadata.map(get);
+
+        let result = PyDict::new(py);
+        result.set_item("taxdump_date", &data.extras.taxdump_date)?;
+        result.set_item("taxdump", field(|metadata| &metadata.taxdump))?;
+        result.set_item(
+            "merged_checksum",
+            field(|metadata| &metadata.merged_checksum),
+        )?;
+        result.set_item("datadir", field(|metadata| &metadata.datadir))?;
+        result.set_item("built_at", field(|metadata| &metadata.built_at))?;
+        result.set_item(
+            "library_version",
+            field(|metadata| &metadata.library_version),
+        )?;
+        Ok(result.into())
+    }
+
+    /// Get an immutable handle sharing the current cache contents
+    ///
+    /// Changes to this cache don't affect the frozen handle, and methods changing the frozen
+    /// handle raise an error.
+    pub fn freeze(&self) -> PyTaxonCache {
+        PyTaxonCache {
+            readonly: self.readonly,
+            ..PyTaxonCache::from_data(self.read(), true)
+        }
+    }
+
+    /// Whether this is a frozen handle created by freeze() or open_readonly()
+    #[getter]
+    pub fn frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Open a cache file as a read-only cache
+    ///
+    /// Like a frozen cache, but save() and save_mmap() raise an error too, and so do copies made
+    /// with freeze() or copy.copy().
+    #[staticmethod]
+    #[args(progress_callback = "None")]
+    pub fn open_readonly(
+        py: Python,
+        cachefile: &PyAny,
+        progress_callback: Option<PyObject>,
+    ) -> PyResult<PyTaxonCache> {
+        let cache = PyTaxonCache::new(py, None)?;
+        cache.load(py, cachefile, progress_callback, false)?;
+        Ok(PyTaxonCache {
+            readonly: true,
+            ..PyTaxonCache::from_data(cache.read(), true)
+        })
+    }
+
+    /// Whether this is a read-only cache opened with open_readonly()
+    #[getter]
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Check the tax IDs and organism names of the MIBiG entries in datadir against the cache
+    pub fn validate_datadir(&self, py: Python, datadir: &PyAny) -> PyResult<PyValidationReport> {
+        let data = self.read();
+        let datadir = extract_path(datadir)?;
+
+        let data_ref = &*data;
+        let report = py.allow_threads(move || -> Result<_, PyMibigTaxonError> {
+            let mut report = PyValidationReport::default();
+            for entry in validate::read_datadir(&datadir)? {
+                report.checked += 1;
+                let tax_id = data_ref.resolve(entry.tax_id);
+                let ncbi_entry = match data_ref.cache.get(tax_id) {
+                    Some(ncbi_entry) => ncbi_entry,
+                    None => {
+                        report.missing.push((entry.entry, entry.tax_id));
+                        continue;
+                    }
+                };
+                if tax_id != entry.tax_id {
+                    report
+                        .deprecated
+                        .push((entry.entry.clone(), entry.tax_id, tax_id));
+                }
+                if let Some(name) = entry.name {
+                    let known_name = validate::same_name(&name, ncbi_entry.name)
+                        || data_ref
+                            .synonyms_of(tax_id)
+                            .iter()
+                            .any(|synonym| validate::same_name(&name, synonym));
+                    if !known_name {
+                        report.name_mismatches.push((
+                            entry.entry,
+                            tax_id,
+                            name,
+                            ncbi_entry.name.to_string(),
+                        ));
+                    }
+                }
+            }
+            Ok(report)
+        })?;
+        Ok(report)
+    }
+
+    /// Count the antiSMASH taxa of the MIBiG entries in datadir, e.g. for release statistics
+    ///
+    /// Deprecated tax IDs are resolved, and custom mapping rules apply like for
+    /// get_antismash_taxon().
+    pub fn tabulate_antismash_taxa(
+        &self,
+        py: Python,
+        datadir: &PyAny,
+    ) -> PyResult<PyTaxonTabulation> {
+        let data = self.read();
+        let datadir = extract_path(datadir)?;
+
+        let data_ref = &*data;
+        let table = py.allow_threads(move || -> Result<_, PyMibigTaxonError> {
+            let mut table = PyTaxonTabulation::default();
+            for entry in validate::read_datadir(&datadir)? {
+                table.entries += 1;
+                let tax_id = data_ref.resolve(entry.tax_id);
+                let ncbi_entry = match data_ref.cache.get(tax_id) {
+                    Some(ncbi_entry) => ncbi_entry,
+                    None => {
+                        table.missing.push((entry.entry, entry.tax_id));
+                        continue;
+                    }
+                };
+                match data_ref.match_taxon(&ncbi_entry) {
+                    Ok(found) => *table.counts.entry(found.taxon).or_default() += 1,
+                    Err(PyMibigTaxonError::InvalidAntismashTaxon { rank, name }) => {
+                        table.unclassifiable.push((entry.entry, tax_id, rank, name));
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(table)
+        })?;
+        Ok(table)
+    }
+
+    /// Compare this cache to a newer one, e.g. for a changelog between MIBiG releases
+    ///
+    /// All lists in the result are sorted by tax ID. Re-parented IDs are only found for entries
+    /// with tree information in both caches.
+    pub fn diff(&self, py: Python, other: PyRef<PyTaxonCache>) -> PyCacheDiff {
+        let old = self.read();
+        let new = other.read();
+
+        let (old, new) = (&*old, &*new);
+        py.allow_threads(move || {
+            let mut diff = PyCacheDiff::default();
+            for entry in new.cache.iter() {
+                let tax_id = &entry.tax_id;
+                let old_entry = match old.cache.get(*tax_id) {
+                    Some(old_entry) => old_entry,
+                    None => {
+                        diff.added.push(*tax_id);
+                        continue;
+                    }
+                };
+                if old_entry.name != entry.name {
+                    diff.renamed.push((
+                        *tax_id,
+                        old_entry.name.to_string(),
+                        entry.name.to_string(),
+                    ));
+                }
+                if let (Some(old_node), Some(node)) =
+                    (old.extras.nodes.get(tax_id), new.extras.nodes.get(tax_id))
+                {
+                    if old_node.parent_id != node.parent_id {
+                        diff.reparented
+                            .push((*tax_id, old_node.parent_id, node.parent_id));
+                    }
+                }
+            }
+            diff.removed = old
+                .cache
+                .tax_ids()
+                .filter(|tax_id| !new.cache.contains(*tax_id))
+                .collect();
+
+            diff.added.sort_unstable();
+            diff.removed.sort_unstable();
+            diff.renamed.sort_unstable_by_key(|(tax_id, _, _)| *tax_id);
+            diff.reparented.sort_unstable();
+            diff
+        })
+    }
+
+    /// Add the accessions from an NCBI accession2taxid file to the accession index
+    ///
+    /// The file can be gzip compressed, as NCBI ships them. The index isn't saved or pickled with
+    /// the cache. Lookups wait while the accessions are merged into the index. Returns the number
+    /// of accessions in the index.
+    #[args(progress_callback = "None")]
+    pub fn load_accession2taxid(
+        &self,
+        py: Python,
+        path: &PyAny,
+        progress_callback: Option<PyObject>,
+    ) -> PyResult<usize> {
+        self.check_frozen()?;
+        let path = extract_path(path)?;
+        let progress = Progress::new(progress_callback);
+
+        let _guard = self.lock_writer()?;
+        let data_lock = &self.data;
+        let progress_ref = &progress;
+        let result = py.allow_threads(move || -> Result<usize, MibigTaxonError> {
+            let file = fs::File::open(path)?;
+            let size = file.metadata()?.len();
+            let input =
+                compression::open_decompressed(progress_ref.wrap("accession2taxid", file, size))?;
+            let added = AccessionIndex::read_sorted(input)?;
+
+            // Merge in place instead of building a second index next to the existing one, so
+            // lookups wait for the merge. The index is only copied if other handles share it.
+            let mut data = data_lock.write().unwrap_or_else(PoisonError::into_inner);
+            let accessions = &mut Arc::make_mut(&mut data).accessions;
+            Arc::make_mut(accessions).merge(added);
+            Ok(accessions.len())
+        });
+        if let Some(err) = progress.take_error() {
+            return Err(err);
+        }
+        Ok(result.map_err(PyMibigTaxonError::from)?)
+    }
+
+    /// Get the tax ID of a nucleotide accession, with or without version
+    ///
+    /// This needs accessions loaded with load_accession2taxid().
+    pub fn get_taxid_by_accession(&self, accession: &str) -> PyResult<i64> {
+        let data = self.read();
+        match data.accessions.get(accession) {
+            Some(tax_id) => Ok(tax_id),
+            None => {
+                let err = PyMibigTaxonError::AccessionNotFound(accession.to_string());
+                Err(PyErr::from(err))
+            }
+        }
+    }
+
+    /// Export all entries, or only the ones in ids, to a JSON file
+    #[args(ids = "None")]
+    pub fn export_json(&self, py: Python, path: &PyAny, ids: Option<Vec<i64>>) -> PyResult<usize> {
+        let data = self.read();
+        let path = extract_path(path)?;
+
+        let cache = &data.cache;
+        let extras = &data.extras;
+        let size = py.allow_threads(move || -> Result<usize, PyMibigTaxonError> {
+            let entries = export::select_entries(cache, ids.as_deref())?;
+            let mut out =
+                io::BufWriter::new(fs::File::create(path).map_err(MibigTaxonError::from)?);
+            export::write_json(&entries, extras, &mut out)?;
+            out.flush().map_err(MibigTaxonError::from)?;
+            Ok(entries.len())
+        })?;
         Ok(size)
     }
 
-    #[args(allow_deprecated = "false")]
-    pub fn get_name_by_id(&self, id: &PyLong, allow_deprecated: bool) -> PyResult<String> {
-        let tax_id: i64 = id.extract()?;
+    /// Export all entries, or only the ones in ids, to a tab-separated file
+    #[args(ids = "None")]
+    pub fn export_tsv(&self, py: Python, path: &PyAny, ids: Option<Vec<i64>>) -> PyResult<usize> {
+        let data = self.read();
+        let path = extract_path(path)?;
 
-        if let Some(entry) = self.cache.mappings.get(&tax_id) {
-            return Ok(entry.name.clone());
-        } else {
-            if !allow_deprecated {
-                let err = PyMibigTaxonError::NotFound(tax_id);
+        let cache = &data.cache;
+        let extras = &data.extras;
+        let size = py.allow_threads(move || -> Result<usize, PyMibigTaxonError> {
+            let entries = export::select_entries(cache, ids.as_deref())?;
+            let mut out =
+                io::BufWriter::new(fs::File::create(path).map_err(MibigTaxonError::from)?);
+            export::write_tsv(&entries, extras, &mut out)?;
+            out.flush().map_err(MibigTaxonError::from)?;
+            Ok(entries.len())
+        })?;
+        Ok(size)
+    }
+
+    /// Write nodes.dmp, names.dmp and merged.dmp to dir, for taxopy.TaxDb
+    ///
+    /// Returns the number of exported nodes. This needs a cache initialised with a nodes_dump.
+    pub fn to_taxopy_db(&self, py: Python, dir: &PyAny) -> PyResult<usize> {
+        let data = self.read();
+        let dir = extract_path(dir)?;
+
+        let cache = &data.cache;
+        let extras = &data.extras;
+        let size = py.allow_threads(move || interop::write_taxdump(cache, extras, &dir))?;
+        Ok(size)
+    }
+
+    /// Write the cache as an SQLite database for ete3's NCBITaxa
+    ///
+    /// Returns the number of exported nodes. An existing file at path is replaced. This needs a
+    /// cache initialised with a nodes_dump.
+    pub fn to_ete3_sqlite(&self, py: Python, path: &PyAny) -> PyResult<usize> {
+        let data = self.read();
+        let path = extract_path(path)?;
+        interop::write_ete3_sqlite(py, &data.cache, &data.extras, &path)
+    }
+
+    /// Get the tree below root_id as a Newick string, e.g. for ete3
+    ///
+    /// If ids are given, the tree only spans these IDs and their ancestors up to root_id.
+    /// labels can be "name" or "tax_id". This needs a cache initialised with a nodes_dump.
+    #[args(root_id = "1", ids = "None", labels = "\"name\"")]
+    pub fn export_newick(
+        &self,
+        root_id: i64,
+        ids: Option<Vec<i64>>,
+        labels: &str,
+    ) -> PyResult<String> {
+        let data = self.read();
+        let root_id = data.resolve_node(root_id)?;
+        let use_names = match labels {
+            "name" => true,
+            "tax_id" => false,
+            _ => {
+                let err = PyMibigTaxonError::InvalidOption {
+                    option: "labels",
+                    value: labels.to_string(),
+                };
                 return Err(PyErr::from(err));
             }
-            if let Some(new_id) = self.cache.deprecated_ids.get(&tax_id) {
-                if let Some(entry) = self.cache.mappings.get(&new_id) {
-                    return Ok(entry.name.clone());
+        };
+
+        let include = match ids {
+            Some(ids) => {
+                let mut include = HashSet::new();
+                for id in ids {
+                    let lineage = data.lineage_of(id)?;
+                    let root_index = match lineage.iter().position(|tax_id| *tax_id == root_id) {
+                        Some(index) => index,
+                        None => {
+                            let err = PyMibigTaxonError::NotDescendant {
+                                tax_id: id,
+                                ancestor_id: root_id,
+                            };
+                            return Err(PyErr::from(err));
+                        }
+                    };
+                    include.extend(&lineage[..=root_index]);
                 }
+                Some(include)
             }
-        }
-        let err = PyMibigTaxonError::NotFound(tax_id);
-        Err(PyErr::from(err))
+            None => None,
+        };
+
+        let cache = &data.cache;
+        let tree = export::newick(
+            root_id,
+            &data.children,
+            include.as_ref(),
+            |tax_id| match cache.get(tax_id) {
+                Some(entry) if use_names => entry.name.to_string(),
+                _ => tax_id.to_string(),
+            },
+        );
+        Ok(tree)
+    }
+
+    /// Save the cache in the format used by open_mmap()
+    pub fn save_mmap(&self, py: Python, cachefile: &PyAny) -> PyResult<usize> {
+        self.check_writable()?;
+        let data = self.read();
+        let cachefile = extract_path(cachefile)?;
+
+        let cache = &data.cache;
+        let size = py
+            .allow_threads(move || mapped::save_path(cache, &cachefile))
+            .map_err(PyMibigTaxonError::from)?;
+        Ok(size)
     }
 
+    /// Open a cache file written by save_mmap() as a read-only memory-mapped cache
+    #[staticmethod]
+    pub fn open_mmap(cachefile: &PyAny) -> PyResult<PyMappedTaxonCache> {
+        let cachefile = extract_path(cachefile)?;
+        let cache = MappedCache::open(&cachefile)?;
+        Ok(PyMappedTaxonCache { cache })
+    }
+
+    /// Get the name of a tax ID
+    ///
+    /// With on_missing="none", unknown IDs return None instead of raising a TaxonNotFoundError.
+    #[args(allow_deprecated = "false", on_missing = "\"raise\"")]
+    pub fn get_name_by_id(
+        &self,
+        py: Python,
+        id: i64,
+        allow_deprecated: bool,
+        on_missing: &str,
+    ) -> PyResult<Option<String>> {
+        let on_missing = OnMissing::parse_single(on_missing)?;
+        let data = self.read();
+        let result = data.lookup(id, allow_deprecated, |entry| Ok(entry.name.to_string()));
+        on_missing.handle(py, result)
+    }
+
+    /// Get the antiSMASH taxon of a tax ID, see get_name_by_id() for on_missing
+    #[args(allow_deprecated = "false", on_missing = "\"raise\"")]
+    pub fn get_antismash_taxon(
+        &self,
+        py: Python,
+        id: i64,
+        allow_deprecated: bool,
+        on_missing: &str,
+    ) -> PyResult<Option<String>> {
+        let on_missing = OnMissing::parse_single(on_missing)?;
+        let data = self.read();
+        let result = data.lookup(id, allow_deprecated, |entry| data.antismash_taxon(entry));
+        on_missing.handle(py, result)
+    }
+
+    /// Explain the antiSMASH taxon of a tax ID, or why there is none
+    ///
+    /// Unlike get_antismash_taxon(), this doesn't raise an error for entries that can't be mapped.
     #[args(allow_deprecated = "false")]
-    pub fn get_antismash_taxon(&self, id: &PyLong, allow_deprecated: bool) -> PyResult<String> {
-        let tax_id: i64 = id.extract()?;
+    pub fn classify(&self, id: i64, allow_deprecated: bool) -> PyResult<PyClassification> {
+        let data = self.read();
+        let tax_id = data.resolve_lookup(id, allow_deprecated)?;
+        let entry = &data.cache.entry(tax_id);
 
-        if let Some(entry) = self.cache.mappings.get(&tax_id) {
-            return get_taxon_from_entry(entry);
-        } else {
-            if !allow_deprecated {
-                let err = PyMibigTaxonError::NotFound(tax_id);
-                return Err(PyErr::from(err));
+        let lineage = RANKS
+            .iter()
+            .rev()
+            .filter_map(|rank| {
+                let name = get_rank_from_entry(entry, rank)?;
+                if name.is_empty() || name == "Unknown" {
+                    return None;
+                }
+                Some((rank.to_string(), name.to_string()))
+            })
+            .collect();
+        let mut classification = PyClassification {
+            tax_id,
+            taxon: None,
+            rank: String::new(),
+            name: String::new(),
+            by_rule: false,
+            lineage,
+            nearest_mappable_ancestor: None,
+            ancestor_taxon: None,
+        };
+        match data.match_taxon(entry) {
+            Ok(found) => {
+                classification.taxon = Some(found.taxon);
+                classification.rank = found.rank;
+                classification.name = found.name;
+                classification.by_rule = found.by_rule;
+            }
+            Err(PyMibigTaxonError::InvalidAntismashTaxon { rank, name }) => {
+                classification.rank = rank;
+                classification.name = name;
+                if let Some((ancestor_id, taxon)) = data.mappable_ancestor(entry) {
+                    classification.nearest_mappable_ancestor = Some(ancestor_id);
+                    classification.ancestor_taxon = Some(taxon);
+                }
             }
-            if let Some(new_id) = self.cache.deprecated_ids.get(&tax_id) {
-                if let Some(entry) = self.cache.mappings.get(&new_id) {
-                    return get_taxon_from_entry(entry);
+            Err(err) => return Err(err.into()),
+        }
+        Ok(classification)
+    }
+
+    /// Get the entry of a tax ID, see get_name_by_id() for on_missing
+    #[args(allow_deprecated = "false", on_missing = "\"raise\"")]
+    pub fn get(
+        &self,
+        py: Python,
+        id: i64,
+        allow_deprecated: bool,
+        on_missing: &str,
+    ) -> PyResult<Option<PyTaxonEntry>> {
+        let on_missing = OnMissing::parse_single(on_missing)?;
+        let data = self.read();
+        let result = data.lookup(id, allow_deprecated, |entry| Ok(data.make_entry(entry)));
+        on_missing.handle(py, result)
+    }
+
+    /// Get the name of a possibly deprecated tax ID as (name, resolved_id, was_deprecated)
+    ///
+    /// Unlike get_name_by_id(allow_deprecated=True), this doesn't warn about deprecated IDs.
+    pub fn get_name_by_id_ex(&self, id: i64) -> PyResult<(String, i64, bool)> {
+        let data = self.read();
+        let tax_id = data.find_id(id, true)?;
+        if tax_id != id {
+            logging::debug(&format!("Resolved deprecated tax ID {} to {}", id, tax_id));
+        }
+        let name = data.cache.entry(tax_id).name.to_string();
+        Ok((name, tax_id, tax_id != id))
+    }
+
+    /// Get the names of many tax IDs at once, without holding the GIL
+    ///
+    /// on_missing decides what happens to unknown IDs, and deprecated IDs unless allow_deprecated
+    /// is set: "raise" raises a TaxonNotFoundError, "none" returns None in their place and "skip"
+    /// leaves them out. With return_errors set, the result is a (names, errors) tuple, with
+    /// (id, reason) pairs of the IDs that weren't found. A single DeprecationWarning lists all
+    /// deprecated IDs that were resolved.
+    #[args(
+        allow_deprecated = "false",
+        on_missing = "\"raise\"",
+        return_errors = "false"
+    )]
+    pub fn get_names_by_ids(
+        &self,
+        py: Python,
+        ids: Vec<i64>,
+        allow_deprecated: bool,
+        on_missing: &str,
+        return_errors: bool,
+    ) -> PyResult<PyObject> {
+        let on_missing = OnMissing::parse(on_missing)?;
+        let data = self.read();
+
+        let data_ref = &*data;
+        let (names, deprecated, errors) =
+            py.allow_threads(move || -> Result<_, PyMibigTaxonError> {
+                let mut names = Vec::with_capacity(ids.len());
+                let mut deprecated = Vec::new();
+                let mut errors = Vec::new();
+                for id in ids {
+                    let tax_id = match data_ref.find_id(id, allow_deprecated) {
+                        Ok(tax_id) => tax_id,
+                        Err(err) if on_missing == OnMissing::Raise => return Err(err),
+                        Err(err) => {
+                            errors.push((id, data_ref.missing_reason(id, err)));
+                            if on_missing == OnMissing::None {
+                                names.push(None);
+                            }
+                            continue;
+                        }
+                    };
+                    if tax_id != id {
+                        deprecated.push((id, tax_id));
+                    }
+                    names.push(Some(data_ref.cache.entry(tax_id).name.to_string()));
                 }
+                Ok((names, deprecated, errors))
+            })?;
+        warn_deprecated_batch(deprecated)?;
+        if return_errors {
+            Ok((names, errors).into_py(py))
+        } else {
+            Ok(names.into_py(py))
+        }
+    }
+
+    /// Awaitable version of get_names_by_ids(), running in the default executor of the event loop
+    #[args(args = "*", kwargs = "**")]
+    pub fn get_names_by_ids_async(
+        slf: PyRef<Self>,
+        py: Python,
+        args: &PyTuple,
+        kwargs: Option<&PyDict>,
+    ) -> PyResult<PyObject> {
+        run_in_executor(py, slf.into_py(py), "get_names_by_ids", args, kwargs)
+    }
+
+    /// Map a whole array of tax IDs to names, e.g. a numpy int64 array or a pyarrow array
+    ///
+    /// Returns an array of the same kind as ids, or a list for other sequences. Unknown IDs and
+    /// nulls map to None. With allow_deprecated, a single DeprecationWarning lists all deprecated
+    /// IDs that were resolved.
+    #[args(allow_deprecated = "false")]
+    pub fn map_ids_to_names(
+        &self,
+        py: Python,
+        ids: &PyAny,
+        allow_deprecated: bool,
+    ) -> PyResult<PyObject> {
+        let data = self.read();
+        let tax_ids = vectorized::extract_ids(ids)?;
+
+        let data_ref = &*data;
+        let (names, deprecated) = py.allow_threads(move || {
+            let mut deprecated = Vec::new();
+            let names: Vec<Option<&str>> = tax_ids
+                .iter()
+                .map(|id| {
+                    let id = (*id)?;
+                    let tax_id = data_ref.find_id(id, allow_deprecated).ok()?;
+                    if tax_id != id {
+                        deprecated.push((id, tax_id));
+                    }
+                    Some(data_ref.cache.entry(tax_id).name)
+                })
+                .collect();
+            (names, deprecated)
+        });
+        warn_deprecated_batch(deprecated)?;
+        vectorized::to_array(py, ids, &names, false)
+    }
+
+    /// Map a whole array of tax IDs to antiSMASH taxa, see map_ids_to_names()
+    ///
+    /// IDs without an antiSMASH taxon map to None. pyarrow results are dictionary-encoded.
+    #[args(allow_deprecated = "false")]
+    pub fn map_ids_to_antismash_taxa(
+        &self,
+        py: Python,
+        ids: &PyAny,
+        allow_deprecated: bool,
+    ) -> PyResult<PyObject> {
+        let data = self.read();
+        let tax_ids = vectorized::extract_ids(ids)?;
+
+        let data_ref = &*data;
+        let (tax_ids, taxa, deprecated) = py.allow_threads(move || {
+            let mut deprecated = Vec::new();
+            let mut taxa: HashMap<Option<i64>, Option<String>> = HashMap::new();
+            for id in &tax_ids {
+                taxa.entry(*id).or_insert_with(|| {
+                    let id = (*id)?;
+                    let tax_id = data_ref.find_id(id, allow_deprecated).ok()?;
+                    if tax_id != id {
+                        deprecated.push((id, tax_id));
+                    }
+                    data_ref.antismash_taxon(&data_ref.cache.entry(tax_id)).ok()
+                });
             }
+            (tax_ids, taxa, deprecated)
+        });
+        warn_deprecated_batch(deprecated)?;
+        let values: Vec<Option<&str>> = tax_ids.iter().map(|id| taxa[id].as_deref()).collect();
+        vectorized::to_array(py, ids, &values, true)
+    }
+
+    /// Get the synonyms, equivalent names and common names of a tax ID
+    ///
+    /// This needs a cache initialised with a names_dump.
+    #[args(allow_deprecated = "false")]
+    pub fn get_synonyms(&self, id: i64, allow_deprecated: bool) -> PyResult<Vec<String>> {
+        let data = self.read();
+        let tax_id = data.resolve_lookup(id, allow_deprecated)?;
+        Ok(data.synonyms_of(tax_id))
+    }
+
+    /// Get the lineage of a tax ID as a string
+    ///
+    /// style is "ncbi" for "Bacteria; Actinomycetota; Actinomycetes", leaving out unknown ranks,
+    /// or "gtdb" for "d__Bacteria;p__Actinomycetota;c__Actinomycetes", keeping them empty.
+    /// ranks defaults to superkingdom, phylum, class, order, family, genus and species.
+    #[args(
+        style = "\"ncbi\"",
+        delimiter = "None",
+        ranks = "None",
+        allow_deprecated = "false"
+    )]
+    pub fn get_lineage_string(
+        &self,
+        id: i64,
+        style: &str,
+        delimiter: Option<&str>,
+        ranks: Option<Vec<String>>,
+        allow_deprecated: bool,
+    ) -> PyResult<String> {
+        let style = LineageStyle::parse(style)?;
+        let data = self.read();
+        let ranks: Vec<String> = match ranks {
+            Some(ranks) => ranks
+                .iter()
+                .map(|rank| data.aliases.rank(rank).to_string())
+                .collect(),
+            None => lineage::LINEAGE_RANKS
+                .iter()
+                .map(|rank| rank.to_string())
+                .collect(),
+        };
+        if let Some(rank) = ranks.iter().find(|rank| !RANKS.contains(&rank.as_str())) {
+            return Err(PyMibigTaxonError::InvalidRank(rank.clone()).into());
+        }
+
+        let tax_id = data.resolve_lookup(id, allow_deprecated)?;
+        let delimiter = delimiter.unwrap_or_else(|| style.default_delimiter());
+        let own_rank = data
+            .extras
+            .nodes
+            .get(&tax_id)
+            .map(|node| data.aliases.rank(&node.rank));
+        Ok(lineage::format(
+            &data.cache.entry(tax_id),
+            own_rank,
+            &ranks,
+            style,
+            delimiter,
+        ))
+    }
+
+    /// Get the NCBI rank of a tax ID, e.g. "species", "strain" or "no rank"
+    ///
+    /// This needs a cache initialised with a nodes_dump.
+    #[args(allow_deprecated = "false")]
+    pub fn get_rank_by_id(&self, id: i64, allow_deprecated: bool) -> PyResult<String> {
+        let data = self.read();
+        let tax_id = data.resolve_lookup(id, allow_deprecated)?;
+        match data.extras.nodes.get(&tax_id) {
+            Some(node) => Ok(node.rank.clone()),
+            None => Err(PyErr::from(PyMibigTaxonError::NoLineage(id))),
+        }
+    }
+
+    /// Get the tax ID of the parent node, or None for the root node
+    ///
+    /// This needs a cache initialised with a nodes_dump.
+    #[args(allow_deprecated = "false")]
+    pub fn get_parent_id(&self, id: i64, allow_deprecated: bool) -> PyResult<Option<i64>> {
+        let data = self.read();
+        let tax_id = data.resolve_lookup(id, allow_deprecated)?;
+        match data.extras.nodes.get(&tax_id) {
+            Some(node) => Ok(parent_of(tax_id, node)),
+            None => Err(PyErr::from(PyMibigTaxonError::NoLineage(id))),
         }
-        let err = PyMibigTaxonError::NotFound(tax_id);
-        Err(PyErr::from(err))
     }
 
+    /// Get the name of the GenBank division of a tax ID, e.g. "Bacteria" or "Plants and Fungi"
+    ///
+    /// This needs a cache initialised with a nodes_dump and a division_dump.
     #[args(allow_deprecated = "false")]
-    pub fn get(&self, id: &PyLong, allow_deprecated: bool) -> PyResult<PyTaxonEntry> {
-        let tax_id: i64 = id.extract()?;
+    pub fn get_division(&self, id: i64, allow_deprecated: bool) -> PyResult<Option<String>> {
+        let data = self.read();
+        let node = data.node_of(id, allow_deprecated)?;
+        let division = node
+            .division_id
+            .and_then(|division_id| data.extras.divisions.get(&division_id));
+        Ok(division.map(|division| division.name.clone()))
+    }
 
-        if let Some(entry) = self.cache.mappings.get(&tax_id) {
-            return Ok(PyTaxonEntry::from(entry));
+    /// Get the ID of the genetic code of a tax ID, i.e. the NCBI translation table number
+    ///
+    /// With mitochondrial set, get the genetic code used in mitochondria instead. This needs a
+    /// cache initialised with a nodes_dump.
+    #[args(mitochondrial = "false", allow_deprecated = "false")]
+    pub fn get_genetic_code(
+        &self,
+        id: i64,
+        mitochondrial: bool,
+        allow_deprecated: bool,
+    ) -> PyResult<Option<i64>> {
+        let data = self.read();
+        let node = data.node_of(id, allow_deprecated)?;
+        if mitochondrial {
+            Ok(node.mito_code_id)
         } else {
-            if !allow_deprecated {
-                let err = PyMibigTaxonError::NotFound(tax_id);
-                return Err(PyErr::from(err));
+            Ok(node.genetic_code_id)
+        }
+    }
+
+    /// Get the name of the genetic code of a tax ID, e.g. "Bacterial, Archaeal and Plant Plastid"
+    ///
+    /// See get_genetic_code(), this also needs a cache initialised with a gencode_dump.
+    #[args(mitochondrial = "false", allow_deprecated = "false")]
+    pub fn get_genetic_code_name(
+        &self,
+        id: i64,
+        mitochondrial: bool,
+        allow_deprecated: bool,
+    ) -> PyResult<Option<String>> {
+        let code_id = self.get_genetic_code(id, mitochondrial, allow_deprecated)?;
+        let data = self.read();
+        Ok(code_id.and_then(|code_id| data.extras.genetic_codes.get(&code_id).cloned()))
+    }
+
+    /// Get all tax IDs with the given scientific name, ignoring case
+    ///
+    /// If include_synonyms is set, also match synonyms, equivalent names and common names.
+    /// If nothing matches, other names of the taxon from the name aliases are tried, so both
+    /// "Firmicutes" and "Bacillota" work with caches from before and after the rename.
+    #[args(include_synonyms = "false")]
+    pub fn get_ids_by_name(&self, name: &str, include_synonyms: bool) -> Vec<i64> {
+        self.read().ids_by_name(name, include_synonyms)
+    }
+
+    /// Get the antiSMASH taxon for an organism name
+    ///
+    /// The name has to match exactly one tax ID, see get_ids_by_name() for the matching rules.
+    /// If allow_deprecated is set, former names of a taxon (synonyms and equivalent names) are
+    /// tried when no scientific name matches, with a DeprecationWarning naming the current name,
+    /// and deprecated IDs are resolved like get_antismash_taxon() does.
+    #[args(allow_deprecated = "false", include_synonyms = "false")]
+    pub fn get_antismash_taxon_by_name(
+        &self,
+        name: &str,
+        allow_deprecated: bool,
+        include_synonyms: bool,
+    ) -> PyResult<String> {
+        let data = self.read();
+        let mut tax_ids = data.ids_by_name(name, include_synonyms);
+        let mut former_name = false;
+        if tax_ids.is_empty() && allow_deprecated {
+            tax_ids = data.ids_with_former_name(&name.to_lowercase());
+            former_name = true;
+        }
+        match tax_ids.as_slice() {
+            [tax_id] => data.lookup(*tax_id, allow_deprecated, |entry| {
+                if former_name {
+                    warn_deprecated_name(name, entry.name)?;
+                }
+                data.antismash_taxon(entry)
+            }),
+            [] => Err(PyErr::from(PyMibigTaxonError::NameNotFound(
+                name.to_string(),
+            ))),
+            _ => Err(PyErr::from(PyMibigTaxonError::AmbiguousName(
+                name.to_string(),
+                tax_ids,
+            ))),
+        }
+    }
+
+    /// Search scientific names and synonyms by prefix and edit distance
+    ///
+    /// Returns up to limit (name, tax_id, score) tuples, best matches first. Matching ignores case.
+    #[args(limit = "10", fuzzy = "true")]
+    pub fn search_names(
+        &self,
+        py: Python,
+        query: &str,
+        limit: usize,
+        fuzzy: bool,
+    ) -> Vec<(String, i64, f64)> {
+        let data = self.read();
+        let query = search::NameQuery::new(query);
+
+        let data_ref = &*data;
+        py.allow_threads(move || {
+            // The name indexes hold each lowercase name once, however many entries share it
+            let mut hits = Vec::new();
+            for (key, tax_ids) in &data_ref.name_index {
+                if let Some(score) = query.score(key, fuzzy) {
+                    for tax_id in tax_ids {
+                        let name = data_ref.cache.entry(*tax_id).name.to_string();
+                        hits.push((name, *tax_id, score));
+                    }
+                }
             }
-            if let Some(new_id) = self.cache.deprecated_ids.get(&tax_id) {
-                if let Some(entry) = self.cache.mappings.get(&new_id) {
-                    return Ok(PyTaxonEntry::from(entry));
+            for (key, tax_ids) in &data_ref.synonym_index {
+                if let Some(score) = query.score(key, fuzzy) {
+                    for tax_id in tax_ids {
+                        let synonyms = data_ref.extras.names.get(tax_id).into_iter().flatten();
+                        for synonym in
+                            synonyms.filter(|synonym| synonym.name.to_lowercase() == *key)
+                        {
+                            hits.push((synonym.name.clone(), *tax_id, score));
+                        }
+                    }
                 }
             }
+            search::rank_hits(&mut hits, limit);
+            hits
+        })
+    }
+
+    /// Get the tax ID of the lowest common ancestor of all IDs
+    ///
+    /// Deprecated IDs are resolved to their replacements. This needs a cache initialised with
+    /// a nodes_dump.
+    pub fn get_lca(&self, ids: Vec<i64>) -> PyResult<i64> {
+        let data = self.read();
+        Ok(data.lca(&ids)?)
+    }
+
+    /// Get the entry of the lowest common ancestor of all IDs
+    pub fn get_lca_entry(&self, ids: Vec<i64>) -> PyResult<PyTaxonEntry> {
+        let data = self.read();
+        let tax_id = data.lca(&ids)?;
+
+        match data.cache.get(tax_id) {
+            Some(entry) => Ok(data.make_entry(&entry)),
+            None => Err(PyErr::from(PyMibigTaxonError::NotFound(tax_id))),
         }
-        let err = PyMibigTaxonError::NotFound(tax_id);
-        Err(PyErr::from(err))
     }
-}
 
-fn get_taxon_from_entry(entry: &NcbiTaxEntry) -> PyResult<String> {
-    match entry.superkingdom.as_str() {
-        "Archaea" | "Bacteria" => return Ok("bacteria".to_string()),
-        "Eukaryota" => match entry.kingdom.as_str() {
-            "Fungi" => return Ok("fungi".to_string()),
-            "Viridiplantae" => return Ok("plants".to_string()),
-            "Unknown" => match entry.phylum.as_str() {
-                "Rhodophyta" | "Bacillariophyta" => return Ok("plants".to_string()),
-                "Unknown" => match entry.class.as_str() {
-                    "Dinophyceae" => return Ok("plants".to_string()),
-                    _ => {
-                        let err = PyMibigTaxonError::InvalidAntismashTaxon(entry.class.clone());
-                        return Err(PyErr::from(err));
-                    }
-                },
-                _ => {
-                    let err = PyMibigTaxonError::InvalidAntismashTaxon(entry.phylum.clone());
-                    return Err(PyErr::from(err));
+    /// Get the current tax ID for a possibly deprecated ID, following chains of merged IDs
+    pub fn resolve_id(&self, id: i64) -> PyResult<i64> {
+        let data = self.read();
+        let tax_id = data.resolve(id);
+        if !data.cache.contains(tax_id) {
+            let err = PyMibigTaxonError::NotFound(id);
+            return Err(PyErr::from(err));
+        }
+        Ok(tax_id)
+    }
+
+    /// Check if id is a deprecated tax ID that was merged into another one
+    ///
+    /// Unknown IDs are not deprecated, use resolve_id() to get the replacement of a deprecated ID.
+    pub fn is_deprecated(&self, id: i64) -> bool {
+        let data = self.read();
+        data.cache.deprecated_ids.contains_key(&id)
+    }
+
+    /// Mapping of deprecated tax IDs to the IDs they were merged into
+    #[getter]
+    pub fn deprecated_ids(&self) -> HashMap<i64, i64> {
+        let data = self.read();
+        data.cache.deprecated_ids.clone()
+    }
+
+    /// Check if child_id is somewhere below ancestor_id in the taxonomic tree
+    pub fn is_descendant_of(&self, child_id: i64, ancestor_id: i64) -> PyResult<bool> {
+        let data = self.read();
+        let lineage = data.lineage_of(child_id)?;
+        let ancestor_id = data.resolve_node(ancestor_id)?;
+        Ok(lineage[1..].contains(&ancestor_id))
+    }
+
+    /// Get the tax IDs of all children of a node, or all of its descendants if recursive is set
+    #[args(recursive = "false")]
+    pub fn get_children(&self, id: i64, recursive: bool) -> PyResult<Vec<i64>> {
+        let data = self.read();
+        let tax_id = data.resolve_node(id)?;
+
+        let mut children = Vec::new();
+        let mut queue = vec![tax_id];
+        while let Some(current) = queue.pop() {
+            if let Some(direct_children) = data.children.get(&current) {
+                children.extend(direct_children);
+                if recursive {
+                    queue.extend(direct_children);
                 }
-            },
-            _ => {
-                let err = PyMibigTaxonError::InvalidAntismashTaxon(entry.kingdom.clone());
-                return Err(PyErr::from(err));
             }
-        },
-        // Many metagenomes are superkingdom "Unknown" but still bacterial
-        _ => return Ok("bacteria".to_string()),
+        }
+        children.sort_unstable();
+        Ok(children)
+    }
+
+    /// Whether a tax ID is from an environmental or metagenome sample, e.g. "uncultured bacterium"
+    ///
+    /// Checks the names of the ID and its ancestors, the ancestors are only known for caches
+    /// initialised with a nodes_dump.
+    #[args(allow_deprecated = "false")]
+    pub fn is_environmental(&self, id: i64, allow_deprecated: bool) -> PyResult<bool> {
+        let data = self.read();
+        let tax_id = data.resolve_lookup(id, allow_deprecated)?;
+        Ok(data.lineage_has_name(tax_id, lineage::is_environmental_name))
     }
+
+    /// Whether a tax ID is in a placeholder taxon like "unclassified Streptomyces", see
+    /// is_environmental()
+    #[args(allow_deprecated = "false")]
+    pub fn is_unclassified(&self, id: i64, allow_deprecated: bool) -> PyResult<bool> {
+        let data = self.read();
+        let tax_id = data.resolve_lookup(id, allow_deprecated)?;
+        Ok(data.lineage_has_name(tax_id, lineage::is_unclassified_name))
+    }
+
+    /// Get the tax IDs of all descendants of a node with the given NCBI rank, e.g. all genera in
+    /// a phylum
+    ///
+    /// This needs a cache initialised with a nodes_dump.
+    pub fn get_descendants_at_rank(
+        &self,
+        py: Python,
+        root_id: i64,
+        rank: &str,
+    ) -> PyResult<Vec<i64>> {
+        let data = self.read();
+        let root_id = data.resolve_node(root_id)?;
+
+        let data_ref = &*data;
+        let descendants = py.allow_threads(move || {
+            let mut descendants = Vec::new();
+            let mut queue = vec![root_id];
+            while let Some(current) = queue.pop() {
+                if let Some(children) = data_ref.children.get(&current) {
+                    for child_id in children {
+                        let is_match = data_ref.extras.nodes.get(child_id).is_some_and(|node| {
+                            data_ref.aliases.rank(&node.rank) == data_ref.aliases.rank(rank)
+                        });
+                        if is_match {
+                            descendants.push(*child_id);
+                        }
+                    }
+                    queue.extend(children);
+                }
+            }
+            descendants.sort_unstable();
+            descendants
+        });
+        Ok(descendants)
+    }
+
+    /// Get the tax ID of the closest node with the given NCBI rank, starting at the ID itself
+    ///
+    /// Returns None if no node in the lineage has that rank. This needs a cache initialised with
+    /// a nodes_dump.
+    pub fn get_ancestor_at_rank(&self, id: i64, rank: &str) -> PyResult<Option<i64>> {
+        let data = self.read();
+        Ok(data.find_ancestor(id, Some(rank), None)?)
+    }
+
+    /// Get the tax ID of the closest node matching a rank, a set of names or both, starting at the
+    /// ID itself
+    ///
+    /// Names are compared case-insensitively, e.g. names={"Fungi", "Viridiplantae"} finds the
+    /// first of these clades in the lineage. Returns None if no node in the lineage matches. This
+    /// needs a cache initialised with a nodes_dump.
+    #[args(predicate_rank = "None", names = "None")]
+    pub fn find_ancestor(
+        &self,
+        id: i64,
+        predicate_rank: Option<&str>,
+        names: Option<&PyAny>,
+    ) -> PyResult<Option<i64>> {
+        let names = match names {
+            // A single name is iterable too, but not as intended
+            Some(names) if names.is_instance::<PyString>()? => {
+                Some(HashSet::from([names.extract::<String>()?.to_lowercase()]))
+            }
+            Some(names) => Some(
+                names
+                    .iter()?
+                    .map(|name| Ok(name?.extract::<String>()?.to_lowercase()))
+                    .collect::<PyResult<HashSet<String>>>()?,
+            ),
+            None => None,
+        };
+        if predicate_rank.is_none() && names.is_none() {
+            return Err(PyTypeError::new_err(
+                "find_ancestor() needs predicate_rank, names or both",
+            ));
+        }
+        let data = self.read();
+        Ok(data.find_ancestor(id, predicate_rank, names.as_ref())?)
+    }
+
+    /// Get the species a strain or other below-species node belongs to
+    pub fn get_species_ancestor(&self, id: i64) -> PyResult<Option<i64>> {
+        self.get_ancestor_at_rank(id, "species")
+    }
+
+    /// Set custom (rank, name, target) rules for the antiSMASH taxon mapping
+    ///
+    /// Rules are checked in order before the built-in mapping, the first rule where the entry's
+    /// lineage has the given name at the given rank wins.
+    pub fn set_antismash_mapping_rules(&self, rules: Vec<MappingRuleSpec>) -> PyResult<()> {
+        self.update(|data| {
+            data.rules = parse_mapping_rules(rules, &data.aliases)?;
+            Ok(())
+        })
+    }
+
+    /// Load curated exceptions to the antiSMASH taxon mapping from a CSV, TOML or JSON file
+    ///
+    /// The format is chosen by the file extension, ".toml" and ".json" for TOML and JSON, CSV
+    /// otherwise.
+    ///
+    /// Exceptions map tax IDs or clade names to antiSMASH taxa, e.g. "Stramenopiles,plants". They
+    /// apply to entries with a matching ID or ancestor, the closest match wins. Clades without a
+    /// rank of their own are only found in caches initialised with a nodes_dump. Exceptions are
+    /// checked after the mapping rules and before the built-in mapping, and replace any
+    /// previously loaded exceptions. Returns the number of exceptions.
+    pub fn load_antismash_exceptions(&self, py: Python, path: &PyAny) -> PyResult<usize> {
+        let path = extract_path(path)?;
+        let text = fs::read_to_string(&path)
+            .map_err(MibigTaxonError::from)
+            .map_err(PyMibigTaxonError::from)?;
+        let exceptions = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ExceptionTable::parse_json(&text)?,
+            Some("toml") => ExceptionTable::parse_toml(py, &text)?,
+            _ => ExceptionTable::parse_csv(&text)?,
+        };
+        let count = exceptions.len();
+        self.update(|data| {
+            data.exceptions = exceptions;
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    /// Set the antiSMASH exceptions as (tax ID or clade name, taxon) pairs, see
+    /// load_antismash_exceptions()
+    pub fn set_antismash_exceptions(&self, exceptions: Vec<ExceptionSpec>) -> PyResult<()> {
+        self.update(|data| {
+            data.exceptions = ExceptionTable::from_specs(exceptions);
+            Ok(())
+        })
+    }
+
+    pub fn get_antismash_exceptions(&self) -> Vec<ExceptionSpec> {
+        self.read().exceptions.specs()
+    }
+
+    /// Treat alias as another name of an NCBI rank, e.g. "domain" for "superkingdom"
+    ///
+    /// Ranks given to other methods and the ranks of nodes are compared after resolving aliases.
+    pub fn add_rank_alias(&self, alias: String, rank: String) -> PyResult<()> {
+        self.update(|data| {
+            data.aliases.add_rank(alias, rank);
+            Ok(())
+        })
+    }
+
+    /// Treat alias as an older name of a taxon, e.g. "Firmicutes" for "Bacillota"
+    ///
+    /// If rank is set, the alias only applies to taxa at that rank. Aliases are used by the
+    /// antiSMASH taxon mapping rules and by get_ids_by_name() if the name itself isn't found.
+    #[args(rank = "None")]
+    pub fn add_name_alias(
+        &self,
+        alias: String,
+        name: String,
+        rank: Option<String>,
+    ) -> PyResult<()> {
+        self.update(|data| {
+            let rank = match rank {
+                Some(rank) => {
+                    let rank = data.aliases.rank(&rank).to_string();
+                    if !RANKS.contains(&rank.as_str()) {
+                        return Err(PyMibigTaxonError::InvalidRank(rank));
+                    }
+                    Some(rank)
+                }
+                None => None,
+            };
+            data.aliases.add_name(alias, name, rank);
+            Ok(())
+        })
+    }
+
+    pub fn get_antismash_mapping_rules(&self) -> Vec<MappingRuleSpec> {
+        let data = self.read();
+        data.rules
+            .iter()
+            .map(|rule| (rule.rank.clone(), rule.name.clone(), rule.target.clone()))
+            .collect()
+    }
+
+    // No Python token argument here, pickle calls this with a NULL argument array that the
+    // fastcall wrapper can't handle
+    pub fn __getstate__(&self) -> PyResult<PickleState> {
+        let data = self.read();
+        let mut buffer = Vec::new();
+        storage::save(&data.cache, &data.extras, &mut buffer).map_err(PyMibigTaxonError::from)?;
+        let buffer = Python::with_gil(|py| PyBytes::new(py, &buffer).into());
+        Ok((
+            buffer,
+            self.get_antismash_mapping_rules(),
+            self.frozen,
+            data.aliases.custom(),
+            data.exceptions.specs(),
+            self.readonly,
+        ))
+    }
+
+    pub fn __setstate__(
+        &mut self,
+        state: (
+            &PyBytes,
+            Vec<MappingRuleSpec>,
+            bool,
+            CustomAliases,
+            Vec<ExceptionSpec>,
+            bool,
+        ),
+    ) -> PyResult<()> {
+        self.check_frozen()?;
+        let (buffer, rules, frozen, (rank_aliases, name_aliases), exceptions, readonly) = state;
+        let (cache, extras) = storage::load(buffer.as_bytes())?;
+        let mut aliases = Aliases::default();
+        for (alias, rank) in rank_aliases {
+            aliases.add_rank(alias, rank);
+        }
+        for (alias, name, rank) in name_aliases {
+            aliases.add_name(alias, name, rank);
+        }
+        let mut data = CacheData {
+            cache,
+            extras,
+            rules: parse_mapping_rules(rules, &aliases)?,
+            exceptions: ExceptionTable::from_specs(exceptions),
+            aliases,
+            ..CacheData::default()
+        };
+        data.rebuild_indexes();
+        self.replace(data);
+        self.frozen = frozen;
+        self.readonly = readonly;
+        Ok(())
+    }
+
+    /// Copies share the contents until either of them is changed
+    pub fn __copy__(&self) -> Self {
+        PyTaxonCache {
+            readonly: self.readonly,
+            ..PyTaxonCache::from_data(self.read(), self.frozen)
+        }
+    }
+
+    pub fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        self.__copy__()
+    }
+
+    pub fn __len__(&self) -> usize {
+        let data = self.read();
+        data.cache.len()
+    }
+
+    pub fn __contains__(&self, tax_id: i64) -> bool {
+        let data = self.read();
+        data.cache.contains(tax_id)
+    }
+
+    pub fn __getitem__(&self, tax_id: i64) -> PyResult<PyTaxonEntry> {
+        let data = self.read();
+        match data.cache.get(tax_id) {
+            Some(entry) => Ok(data.make_entry(&entry)),
+            None => Err(PyKeyError::new_err(tax_id)),
+        }
+    }
+
+    pub fn __iter__(&self) -> TaxIdIterator {
+        TaxIdIterator {
+            inner: self.read().sorted_ids().into_iter(),
+        }
+    }
+
+    /// Iterate over the tax IDs, sorted like __iter__()
+    pub fn ids(&self) -> TaxIdIterator {
+        self.__iter__()
+    }
+
+    /// Iterate over the names of the entries, in tax ID order
+    pub fn names(&self) -> EntryIterator {
+        EntryIterator::new(self.read(), EntryItem::Name)
+    }
+
+    /// Iterate over (tax_id, TaxonEntry) pairs, in tax ID order
+    ///
+    /// Entries are only created as they are reached, so this is cheaper than building a dict.
+    /// Changes to the cache while iterating don't affect the iterator.
+    pub fn entries(&self) -> EntryIterator {
+        EntryIterator::new(self.read(), EntryItem::Entry)
+    }
+}
+
+impl PyTaxonCache {
+    fn check_writable(&self) -> Result<(), PyMibigTaxonError> {
+        if self.readonly {
+            return Err(PyMibigTaxonError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn from_data(data: Arc<CacheData>, frozen: bool) -> PyTaxonCache {
+        PyTaxonCache {
+            data: RwLock::new(data),
+            writer: WriteLock::default(),
+            frozen,
+            readonly: false,
+        }
+    }
+
+    /// Get a snapshot of the current contents
+    fn read(&self) -> Arc<CacheData> {
+        // A panic while holding the lock can't leave the Arc itself in a broken state
+        let data = self.data.read().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(&data)
+    }
+
+    /// Replace the contents, lookups already running keep using their snapshot
+    ///
+    /// Changes based on a snapshot need to hold the write lock, see lock_writer().
+    fn replace(&self, data: CacheData) {
+        *self.data.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(data);
+    }
+
+    /// Change the contents in place, copying them first if they're shared with other handles
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut CacheData) -> Result<T, PyMibigTaxonError>,
+    ) -> PyResult<T> {
+        self.check_frozen()?;
+        let guard = self.lock_writer()?;
+        self.update_locked(&guard, change)
+    }
+
+    /// Like update(), for changes already holding the write lock
+    fn update_locked<T>(
+        &self,
+        _guard: &WriteGuard,
+        change: impl FnOnce(&mut CacheData) -> Result<T, PyMibigTaxonError>,
+    ) -> PyResult<T> {
+        let mut data = self.data.write().unwrap_or_else(PoisonError::into_inner);
+        Ok(change(Arc::make_mut(&mut data))?)
+    }
+
+    /// Take the write lock, waiting with the GIL released if another change is in progress
+    ///
+    /// Hold the guard from taking the snapshot a change is based on until replace().
+    fn lock_writer(&self) -> PyResult<WriteGuard<'_>> {
+        if let Some(guard) = self.writer.try_lock() {
+            return Ok(guard);
+        }
+        let writer = &self.writer;
+        Ok(Python::with_gil(|py| py.allow_threads(|| writer.lock()))?)
+    }
+
+    fn check_frozen(&self) -> Result<(), PyMibigTaxonError> {
+        self.check_writable()?;
+        if self.frozen {
+            return Err(PyMibigTaxonError::Frozen);
+        }
+        Ok(())
+    }
+}
+
+impl Default for CacheData {
+    fn default() -> Self {
+        CacheData {
+            cache: EntryStore::default(),
+            extras: CacheExtras::default(),
+            children: HashMap::new(),
+            name_index: HashMap::new(),
+            synonym_index: HashMap::new(),
+            rules: Vec::new(),
+            exceptions: ExceptionTable::default(),
+            aliases: Aliases::default(),
+            accessions: Arc::new(AccessionIndex::default()),
+            file_size: None,
+        }
+    }
+}
+
+impl CacheData {
+    /// Rebuild the lookup tables derived from the cache contents
+    fn rebuild_indexes(&mut self) {
+        self.children = taxdump::index_children(&self.extras.nodes);
+
+        self.name_index.clear();
+        for entry in self.cache.iter() {
+            self.name_index
+                .entry(entry.name.to_lowercase())
+                .or_default()
+                .push(entry.tax_id);
+        }
+
+        self.synonym_index.clear();
+        for (tax_id, names) in &self.extras.names {
+            for name in names {
+                self.synonym_index
+                    .entry(name.name.to_lowercase())
+                    .or_default()
+                    .push(*tax_id);
+            }
+        }
+    }
+
+    fn prune(&mut self, tax_ids: HashSet<i64>) -> usize {
+        let mut keep = HashSet::new();
+        for tax_id in tax_ids {
+            let tax_id = self.resolve(tax_id);
+            if !self.cache.contains(tax_id) {
+                continue;
+            }
+            keep.extend(taxdump::get_lineage(tax_id, &self.extras.nodes));
+        }
+
+        self.cache.retain(|tax_id| keep.contains(&tax_id));
+        self.extras.nodes.retain(|tax_id, _| keep.contains(tax_id));
+        self.extras.names.retain(|tax_id, _| keep.contains(tax_id));
+        self.extras.manual.retain(|tax_id| keep.contains(tax_id));
+        let resolved: HashMap<i64, i64> = self
+            .cache
+            .deprecated_ids
+            .keys()
+            .map(|old_id| (*old_id, self.resolve(*old_id)))
+            .collect();
+        self.cache
+            .deprecated_ids
+            .retain(|old_id, _| keep.contains(&resolved[old_id]));
+
+        self.rebuild_indexes();
+        self.cache.len()
+    }
+
+    /// Map an entry to an antiSMASH taxon, using the custom rules and tree information if available
+    fn antismash_taxon(&self, entry: &EntryRef) -> PyResult<String> {
+        Ok(self.match_taxon(entry)?.taxon)
+    }
+
+    /// Map an entry with the custom rules first, then the built-in mapping
+    fn match_taxon(&self, entry: &EntryRef) -> Result<TaxonMatch, PyMibigTaxonError> {
+        for rule in &self.rules {
+            let matches = get_rank_from_entry(entry, &rule.rank).is_some_and(|name| {
+                self.aliases.name(&rule.rank, name) == self.aliases.name(&rule.rank, &rule.name)
+            });
+            if matches {
+                return Ok(TaxonMatch {
+                    taxon: rule.target.clone(),
+                    rank: rule.rank.clone(),
+                    name: rule.name.clone(),
+                    by_rule: true,
+                });
+            }
+        }
+        if let Some(found) = self.match_exception(entry) {
+            return Ok(found);
+        }
+        // Works no matter which lineage columns the taxdump fills in for viruses
+        if taxdump::get_lineage(entry.tax_id, &self.extras.nodes).contains(&VIRUSES_TAX_ID) {
+            let rank = self
+                .extras
+                .nodes
+                .get(&VIRUSES_TAX_ID)
+                .map_or("superkingdom", |node| self.aliases.rank(&node.rank));
+            return Ok(TaxonMatch {
+                taxon: "viruses".to_string(),
+                rank: rank.to_string(),
+                name: "Viruses".to_string(),
+                by_rule: false,
+            });
+        }
+        match_taxon(&self.normalise_entry(entry))
+    }
+
+    /// Find the closest exception matching the entry or one of its ancestors
+    fn match_exception(&self, entry: &EntryRef) -> Option<TaxonMatch> {
+        if self.exceptions.is_empty() {
+            return None;
+        }
+        let found = |taxon: &str, rank: &str, name: &str| TaxonMatch {
+            taxon: taxon.to_string(),
+            rank: rank.to_string(),
+            name: name.to_string(),
+            by_rule: true,
+        };
+        // The tree includes clades like Stramenopiles, which have no lineage column
+        for tax_id in taxdump::get_lineage(entry.tax_id, &self.extras.nodes) {
+            let name = self.cache.get(tax_id).map_or("", |ancestor| ancestor.name);
+            let taxon = self
+                .exceptions
+                .by_id(tax_id)
+                .or_else(|| self.exceptions.by_name(name));
+            if let Some(taxon) = taxon {
+                let rank = self
+                    .extras
+                    .nodes
+                    .get(&tax_id)
+                    .map_or("no rank", |node| self.aliases.rank(&node.rank));
+                return Some(found(taxon, rank, name));
+            }
+        }
+        for rank in RANKS.iter() {
+            let name = get_rank_from_entry(entry, rank).unwrap_or_default();
+            if let Some(taxon) = self.exceptions.by_name(name) {
+                return Some(found(taxon, rank, name));
+            }
+        }
+        None
+    }
+
+    /// Find the closest ancestor of an entry that can be mapped to an antiSMASH taxon
+    ///
+    /// Only ancestors in the same superkingdom count, everything above it would map to bacteria
+    /// like other entries without a superkingdom.
+    fn mappable_ancestor(&self, entry: &EntryRef) -> Option<(i64, String)> {
+        let lineage = taxdump::get_lineage(entry.tax_id, &self.extras.nodes);
+        for tax_id in lineage.into_iter().skip(1) {
+            let ancestor = self.cache.get(tax_id)?;
+            if ancestor.superkingdom != entry.superkingdom {
+                return None;
+            }
+            if let Ok(found) = self.match_taxon(&ancestor) {
+                return Some((tax_id, found.taxon));
+            }
+        }
+        None
+    }
+
+    /// Replace names in the lineage of an entry that have aliases with their current names
+    fn normalise_entry<'a>(&'a self, entry: &EntryRef<'a>) -> EntryRef<'a> {
+        let mut entry = *entry;
+        for rank in RANKS.iter() {
+            let name = get_rank_from_entry(&entry, rank).unwrap_or_default();
+            let current = self.aliases.name(rank, name);
+            match *rank {
+                "species" => entry.species = current,
+                "genus" => entry.genus = current,
+                "family" => entry.family = current,
+                "order" => entry.order = current,
+                "class" => entry.class = current,
+                "phylum" => entry.phylum = current,
+                "kingdom" => entry.kingdom = current,
+                _ => entry.superkingdom = current,
+            }
+        }
+        entry
+    }
+
+    /// Get all tax IDs with the given name, see TaxonCache.get_ids_by_name()
+    fn ids_by_name(&self, name: &str, include_synonyms: bool) -> Vec<i64> {
+        let mut tax_ids = self.ids_with_name(&name.to_lowercase(), include_synonyms);
+        if tax_ids.is_empty() {
+            for (other_name, rank) in self.aliases.other_names(name) {
+                let other_ids = self.ids_with_name(&other_name, include_synonyms);
+                tax_ids.extend(other_ids.into_iter().filter(|tax_id| {
+                    // Nodes are only known with a nodes_dump, keep the IDs if they're missing
+                    match (rank, self.extras.nodes.get(tax_id)) {
+                        (Some(rank), Some(node)) => self.aliases.rank(&node.rank) == rank,
+                        _ => true,
+                    }
+                }));
+            }
+        }
+        tax_ids.sort_unstable();
+        tax_ids.dedup();
+        tax_ids
+    }
+
+    /// Get the tax IDs for a lowercase former name, i.e. a synonym or equivalent name
+    fn ids_with_former_name(&self, key: &str) -> Vec<i64> {
+        let mut tax_ids: Vec<i64> = self
+            .synonym_index
+            .get(key)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|tax_id| {
+                self.extras
+                    .names
+                    .get(tax_id)
+                    .into_iter()
+                    .flatten()
+                    .any(|name| {
+                        taxdump::FORMER_NAME_CLASSES.contains(&name.name_class.as_str())
+                            && name.name.to_lowercase() == key
+                    })
+            })
+            .collect();
+        tax_ids.sort_unstable();
+        tax_ids.dedup();
+        tax_ids
+    }
+
+    /// Get the tax IDs for a lowercase name, without looking at aliases
+    fn ids_with_name(&self, key: &str, include_synonyms: bool) -> Vec<i64> {
+        let mut tax_ids: Vec<i64> = self.name_index.get(key).cloned().unwrap_or_default();
+        if include_synonyms {
+            if let Some(synonym_ids) = self.synonym_index.get(key) {
+                tax_ids.extend(synonym_ids);
+            }
+        }
+        tax_ids
+    }
+
+    fn sorted_ids(&self) -> Vec<i64> {
+        let mut tax_ids: Vec<i64> = self.cache.tax_ids().collect();
+        tax_ids.sort_unstable();
+        tax_ids
+    }
+
+    /// Build the python entry, including the information not stored in the entry itself
+    fn make_entry(&self, entry: &EntryRef) -> PyTaxonEntry {
+        let mut py_entry = PyTaxonEntry::from(*entry);
+        py_entry.synonyms = self.synonyms_of(entry.tax_id);
+        if let Some(node) = self.extras.nodes.get(&entry.tax_id) {
+            py_entry.rank = Some(node.rank.clone());
+            py_entry.parent_id = parent_of(entry.tax_id, node);
+        }
+        py_entry
+    }
+
+    fn synonyms_of(&self, tax_id: i64) -> Vec<String> {
+        match self.extras.names.get(&tax_id) {
+            Some(names) => names.iter().map(|name| name.name.clone()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Resolve the ID the same way get() does, including the warning for deprecated IDs
+    fn resolve_lookup(&self, id: i64, allow_deprecated: bool) -> PyResult<i64> {
+        let tax_id = self.find_id(id, allow_deprecated)?;
+        if tax_id != id {
+            warn_deprecated(id, tax_id)?;
+        }
+        Ok(tax_id)
+    }
+
+    fn find_id(&self, id: i64, allow_deprecated: bool) -> Result<i64, PyMibigTaxonError> {
+        if self.cache.contains(id) {
+            return Ok(id);
+        }
+        if allow_deprecated {
+            if let Some(new_id) = self.cache.deprecated_ids.get(&id) {
+                if self.cache.contains(*new_id) {
+                    return Ok(*new_id);
+                }
+            }
+        }
+        Err(PyMibigTaxonError::NotFound(id))
+    }
+
+    /// Look up an entry like get() does, including the warning for deprecated IDs
+    fn lookup<T>(
+        &self,
+        id: i64,
+        allow_deprecated: bool,
+        result: impl FnOnce(&EntryRef) -> PyResult<T>,
+    ) -> PyResult<T> {
+        let tax_id = self.resolve_lookup(id, allow_deprecated)?;
+        result(&self.cache.entry(tax_id))
+    }
+
+    /// Why find_id() failed for an ID, pointing out deprecated IDs that weren't resolved
+    fn missing_reason(&self, id: i64, err: PyMibigTaxonError) -> String {
+        match self.cache.deprecated_ids.get(&id) {
+            Some(new_id) if self.cache.contains(*new_id) => {
+                format!("ID {} is deprecated, use {} instead", id, new_id)
+            }
+            _ => err.to_string(),
+        }
+    }
+
+    /// Follow the deprecated ID mappings until the current ID is reached
+    fn resolve(&self, id: i64) -> i64 {
+        let mut tax_id = id;
+        // Guard against loops in broken merged ID data
+        for _ in 0..=self.cache.deprecated_ids.len() {
+            match self.cache.deprecated_ids.get(&tax_id) {
+                Some(new_id) => tax_id = *new_id,
+                None => break,
+            }
+        }
+        tax_id
+    }
+
+    /// Resolve deprecated IDs and make sure there's tree information for the result
+    fn resolve_node(&self, id: i64) -> Result<i64, PyMibigTaxonError> {
+        let tax_id = self.resolve(id);
+        if !self.cache.contains(tax_id) {
+            return Err(PyMibigTaxonError::NotFound(id));
+        }
+        if !self.extras.nodes.contains_key(&tax_id) {
+            return Err(PyMibigTaxonError::NoLineage(id));
+        }
+        Ok(tax_id)
+    }
+
+    /// Get the tree information of a tax ID, resolving it like get() does
+    fn node_of(&self, id: i64, allow_deprecated: bool) -> PyResult<&TaxonNode> {
+        let tax_id = self.resolve_lookup(id, allow_deprecated)?;
+        match self.extras.nodes.get(&tax_id) {
+            Some(node) => Ok(node),
+            None => Err(PyErr::from(PyMibigTaxonError::NoLineage(id))),
+        }
+    }
+
+    /// Check the names of a tax ID and all of its ancestors in the cache
+    fn lineage_has_name(&self, tax_id: i64, check: impl Fn(&str) -> bool) -> bool {
+        taxdump::get_lineage(tax_id, &self.extras.nodes)
+            .iter()
+            .filter_map(|tax_id| self.cache.get(*tax_id))
+            .any(|entry| check(entry.name))
+    }
+
+    fn lineage_of(&self, id: i64) -> Result<Vec<i64>, PyMibigTaxonError> {
+        let tax_id = self.resolve_node(id)?;
+        Ok(taxdump::get_lineage(tax_id, &self.extras.nodes))
+    }
+
+    /// The tax ID of the lowest common ancestor of all IDs
+    fn lca(&self, ids: &[i64]) -> Result<i64, PyMibigTaxonError> {
+        let mut lineages = Vec::with_capacity(ids.len());
+        for id in ids {
+            lineages.push(self.lineage_of(*id)?);
+        }
+
+        let (first, others) = match lineages.split_first() {
+            Some(split) => split,
+            None => return Err(PyMibigTaxonError::EmptyIdList),
+        };
+        let others: Vec<HashSet<i64>> = others
+            .iter()
+            .map(|lineage| lineage.iter().copied().collect())
+            .collect();
+
+        for tax_id in first {
+            if others.iter().all(|lineage| lineage.contains(tax_id)) {
+                return Ok(*tax_id);
+            }
+        }
+        // Lineages of the same taxonomy always share the root, so this only happens on a broken cache
+        Err(PyMibigTaxonError::NoLineage(first[0]))
+    }
+
+    /// Find the closest node in the lineage of id with the rank and one of the lowercase names
+    fn find_ancestor(
+        &self,
+        id: i64,
+        rank: Option<&str>,
+        names: Option<&HashSet<String>>,
+    ) -> Result<Option<i64>, PyMibigTaxonError> {
+        let lineage = self.lineage_of(id)?;
+        let ancestor = lineage.into_iter().find(|tax_id| {
+            let rank_matches = rank.is_none_or(|rank| {
+                self.extras
+                    .nodes
+                    .get(tax_id)
+                    .is_some_and(|node| self.aliases.rank(&node.rank) == self.aliases.rank(rank))
+            });
+            let name_matches = names.is_none_or(|names| {
+                self.cache
+                    .get(*tax_id)
+                    .is_some_and(|entry| names.contains(&entry.name.to_lowercase()))
+            });
+            rank_matches && name_matches
+        });
+        Ok(ancestor)
+    }
+}
+
+/// Read-only TaxonCache backed by a memory-mapped file
+///
+/// The file contents are shared via the OS page cache between all processes opening the same file.
+#[pyclass(name = "MappedTaxonCache", module = "mibig_taxa")]
+struct PyMappedTaxonCache {
+    cache: MappedCache,
+}
+
+#[pymethods]
+impl PyMappedTaxonCache {
+    /// See TaxonCache.get_name_by_id() for on_missing
+    #[args(allow_deprecated = "false", on_missing = "\"raise\"")]
+    pub fn get_name_by_id(
+        &self,
+        py: Python,
+        id: i64,
+        allow_deprecated: bool,
+        on_missing: &str,
+    ) -> PyResult<Option<String>> {
+        let on_missing = OnMissing::parse_single(on_missing)?;
+        let result = self.resolve(id, allow_deprecated).and_then(|tax_id| {
+            match self.cache.get_name(tax_id) {
+                Some(name) => Ok(name),
+                None => Err(PyErr::from(PyMibigTaxonError::NotFound(id))),
+            }
+        });
+        on_missing.handle(py, result)
+    }
+
+    #[args(allow_deprecated = "false", on_missing = "\"raise\"")]
+    pub fn get_antismash_taxon(
+        &self,
+        py: Python,
+        id: i64,
+        allow_deprecated: bool,
+        on_missing: &str,
+    ) -> PyResult<Option<String>> {
+        let on_missing = OnMissing::parse_single(on_missing)?;
+        let result = self
+            .lookup(id, allow_deprecated)
+            .and_then(|entry| get_taxon_from_entry(&EntryRef::from(&entry)));
+        on_missing.handle(py, result)
+    }
+
+    #[args(allow_deprecated = "false", on_missing = "\"raise\"")]
+    pub fn get(
+        &self,
+        py: Python,
+        id: i64,
+        allow_deprecated: bool,
+        on_missing: &str,
+    ) -> PyResult<Option<PyTaxonEntry>> {
+        let on_missing = OnMissing::parse_single(on_missing)?;
+        let result = self.lookup(id, allow_deprecated);
+        on_missing.handle(
+            py,
+            result.map(|entry| PyTaxonEntry::from(EntryRef::from(&entry))),
+        )
+    }
+
+    /// Get the name of a possibly deprecated tax ID as (name, resolved_id, was_deprecated)
+    pub fn get_name_by_id_ex(&self, id: i64) -> PyResult<(String, i64, bool)> {
+        let tax_id = self.find_id(id, true)?;
+        match self.cache.get_name(tax_id) {
+            Some(name) => Ok((name, tax_id, tax_id != id)),
+            None => Err(PyErr::from(PyMibigTaxonError::NotFound(id))),
+        }
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn __contains__(&self, tax_id: i64) -> bool {
+        self.cache.contains(tax_id)
+    }
+
+    pub fn __getitem__(&self, tax_id: i64) -> PyResult<PyTaxonEntry> {
+        match self.cache.get(tax_id) {
+            Some(entry) => Ok(PyTaxonEntry::from(EntryRef::from(&entry))),
+            None => Err(PyKeyError::new_err(tax_id)),
+        }
+    }
+}
+
+impl PyMappedTaxonCache {
+    fn resolve(&self, tax_id: i64, allow_deprecated: bool) -> PyResult<i64> {
+        let resolved_id = self.find_id(tax_id, allow_deprecated)?;
+        if resolved_id != tax_id {
+            warn_deprecated(tax_id, resolved_id)?;
+        }
+        Ok(resolved_id)
+    }
+
+    fn find_id(&self, tax_id: i64, allow_deprecated: bool) -> Result<i64, PyMibigTaxonError> {
+        if self.cache.contains(tax_id) {
+            return Ok(tax_id);
+        }
+        if allow_deprecated {
+            if let Some(new_id) = self.cache.get_deprecated(tax_id) {
+                return Ok(new_id);
+            }
+        }
+        Err(PyMibigTaxonError::NotFound(tax_id))
+    }
+
+    fn lookup(&self, tax_id: i64, allow_deprecated: bool) -> PyResult<NcbiTaxEntry> {
+        let resolved_id = self.resolve(tax_id, allow_deprecated)?;
+        match self.cache.get(resolved_id) {
+            Some(entry) => Ok(entry),
+            None => Err(PyErr::from(PyMibigTaxonError::NotFound(tax_id))),
+        }
+    }
+}
+
+/// Result of TaxonCache.validate_datadir()
+#[pyclass(name = "ValidationReport", module = "mibig_taxa")]
+#[derive(Default)]
+struct PyValidationReport {
+    /// Number of MIBiG entries checked
+    #[pyo3(get)]
+    checked: usize,
+    /// (entry, tax_id) of entries with tax IDs the cache doesn't know
+    #[pyo3(get)]
+    missing: Vec<(String, i64)>,
+    /// (entry, tax_id, new_tax_id) of entries with deprecated tax IDs
+    #[pyo3(get)]
+    deprecated: Vec<(String, i64, i64)>,
+    /// (entry, tax_id, entry_name, ncbi_name) of entries whose organism name doesn't match NCBI
+    #[pyo3(get)]
+    name_mismatches: Vec<(String, i64, String, String)>,
+}
+
+#[pymethods]
+impl PyValidationReport {
+    /// Whether no problems were found
+    #[getter]
+    pub fn ok(&self) -> bool {
+        self.missing.is_empty() && self.deprecated.is_empty() && self.name_mismatches.is_empty()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "ValidationReport(checked={}, missing={}, deprecated={}, name_mismatches={})",
+            self.checked,
+            self.missing.len(),
+            self.deprecated.len(),
+            self.name_mismatches.len()
+        )
+    }
+}
+
+/// Result of TaxonCache.tabulate_antismash_taxa()
+#[pyclass(name = "TaxonTabulation", module = "mibig_taxa")]
+#[derive(Default)]
+struct PyTaxonTabulation {
+    /// Number of MIBiG entries read
+    #[pyo3(get)]
+    entries: usize,
+    /// Number of entries per antiSMASH taxon
+    #[pyo3(get)]
+    counts: BTreeMap<String, usize>,
+    /// (entry, tax_id, rank, name) of entries that can't be mapped, with the lineage rank and name
+    /// that couldn't be mapped, see TaxonCache.classify() for details
+    #[pyo3(get)]
+    unclassifiable: Vec<(String, i64, String, String)>,
+    /// (entry, tax_id) of entries with tax IDs the cache doesn't know
+    #[pyo3(get)]
+    missing: Vec<(String, i64)>,
+}
+
+#[pymethods]
+impl PyTaxonTabulation {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "TaxonTabulation(entries={}, counts={:?}, unclassifiable={}, missing={})",
+            self.entries,
+            self.counts,
+            self.unclassifiable.len(),
+            self.missing.len()
+        )
+    }
+}
+
+/// Differences between two caches, as returned by TaxonCache.diff()
+#[pyclass(name = "CacheDiff", module = "mibig_taxa")]
+#[derive(Default)]
+struct PyCacheDiff {
+    /// Tax IDs only in the newer cache
+    #[pyo3(get)]
+    added: Vec<i64>,
+    /// Tax IDs only in the older cache
+    #[pyo3(get)]
+    removed: Vec<i64>,
+    /// (tax_id, old_name, new_name) of entries with a changed name
+    #[pyo3(get)]
+    renamed: Vec<(i64, String, String)>,
+    /// (tax_id, old_parent_id, new_parent_id) of entries that moved in the tree
+    #[pyo3(get)]
+    reparented: Vec<(i64, i64, i64)>,
+}
+
+#[pymethods]
+impl PyCacheDiff {
+    /// Whether the caches have the same entries
+    #[getter]
+    pub fn empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.reparented.is_empty()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "CacheDiff(added={}, removed={}, renamed={}, reparented={})",
+            self.added.len(),
+            self.removed.len(),
+            self.renamed.len(),
+            self.reparented.len()
+        )
+    }
+}
+
+/// How a tax ID maps to an antiSMASH taxon, as returned by TaxonCache.classify()
+#[pyclass(name = "Classification", module = "mibig_taxa")]
+struct PyClassification {
+    /// The tax ID, after resolving deprecated IDs
+    #[pyo3(get)]
+    tax_id: i64,
+    /// The antiSMASH taxon, or None if the entry can't be mapped
+    #[pyo3(get)]
+    taxon: Option<String>,
+    /// Rank of the lineage entry the taxon is based on, or that couldn't be mapped
+    #[pyo3(get)]
+    rank: String,
+    /// Name of the lineage entry the taxon is based on, or that couldn't be mapped
+    #[pyo3(get)]
+    name: String,
+    /// Whether a custom mapping rule or antiSMASH exception decided the taxon
+    #[pyo3(get)]
+    by_rule: bool,
+    /// (rank, name) of the known ranks of the entry, from superkingdom to species
+    #[pyo3(get)]
+    lineage: Vec<(String, String)>,
+    /// For entries that can't be mapped, the closest ancestor in the same superkingdom that can,
+    /// if the cache was initialised with a nodes_dump
+    #[pyo3(get)]
+    nearest_mappable_ancestor: Option<i64>,
+    /// The antiSMASH taxon of nearest_mappable_ancestor
+    #[pyo3(get)]
+    ancestor_taxon: Option<String>,
+}
+
+#[pymethods]
+impl PyClassification {
+    /// Whether the entry maps to an antiSMASH taxon
+    #[getter]
+    pub fn mapped(&self) -> bool {
+        self.taxon.is_some()
+    }
+
+    pub fn __repr__(&self) -> String {
+        match &self.taxon {
+            Some(taxon) => format!(
+                "Classification(tax_id={}, taxon={:?}, {}={:?})",
+                self.tax_id, taxon, self.rank, self.name
+            ),
+            None => format!(
+                "Classification(tax_id={}, taxon=None, unmapped {}={:?})",
+                self.tax_id, self.rank, self.name
+            ),
+        }
+    }
+}
+
+/// Iterator over the tax IDs in a TaxonCache
+#[pyclass(module = "mibig_taxa")]
+struct TaxIdIterator {
+    inner: std::vec::IntoIter<i64>,
+}
+
+#[pymethods]
+impl TaxIdIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> Option<i64> {
+        slf.inner.next()
+    }
+}
+
+enum EntryItem {
+    Name,
+    Entry,
+}
+
+/// Iterator over the names or entries of a snapshot of a TaxonCache
+#[pyclass(module = "mibig_taxa")]
+struct EntryIterator {
+    data: Arc<CacheData>,
+    tax_ids: std::vec::IntoIter<i64>,
+    item: EntryItem,
+}
+
+impl EntryIterator {
+    fn new(data: Arc<CacheData>, item: EntryItem) -> EntryIterator {
+        let tax_ids = data.sorted_ids().into_iter();
+        EntryIterator {
+            data,
+            tax_ids,
+            item,
+        }
+    }
+}
+
+#[pymethods]
+impl EntryIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<Self>) -> Option<PyObject> {
+        let tax_id = slf.tax_ids.next()?;
+        let entry = slf.data.cache.entry(tax_id);
+        Python::with_gil(|py| match slf.item {
+            EntryItem::Name => Some(entry.name.to_object(py)),
+            EntryItem::Entry => Some((tax_id, slf.data.make_entry(&entry)).into_py(py)),
+        })
+    }
+}
+
+/// The root node is its own parent in nodes.dmp
+fn parent_of(tax_id: i64, node: &TaxonNode) -> Option<i64> {
+    if node.parent_id == tax_id {
+        None
+    } else {
+        Some(node.parent_id)
+    }
+}
+
+/// Tell the caller that a deprecated ID was resolved, so the data can be updated
+///
+/// The resolution is logged as well, as warnings only show up once per call site by default.
+fn warn_deprecated(old_id: i64, new_id: i64) -> PyResult<()> {
+    logging::debug(&format!(
+        "Resolved deprecated tax ID {} to {}",
+        old_id, new_id
+    ));
+    Python::with_gil(|py| {
+        let message = format!("Tax ID {} is deprecated, use {} instead", old_id, new_id);
+        // pyo3 doesn't wrap DeprecationWarning
+        let category = py.import("builtins")?.getattr("DeprecationWarning")?;
+        PyErr::warn(py, category, &message, 1)
+    })
+}
+
+/// Tell the caller about the deprecated IDs resolved by a batch lookup, with a single warning
+///
+/// Each resolution is logged, like warn_deprecated() does.
+fn warn_deprecated_batch(mut resolved: Vec<(i64, i64)>) -> PyResult<()> {
+    resolved.sort_unstable();
+    resolved.dedup();
+    match resolved.as_slice() {
+        [] => return Ok(()),
+        [(old_id, new_id)] => return warn_deprecated(*old_id, *new_id),
+        _ => {}
+    }
+    if logging::enabled(logging::Level::Debug) {
+        for (old_id, new_id) in &resolved {
+            logging::debug(&format!(
+                "Resolved deprecated tax ID {} to {}",
+                old_id, new_id
+            ));
+        }
+    }
+    let pairs: Vec<String> = resolved
+        .iter()
+        .map(|(old_id, new_id)| format!("{} (use {})", old_id, new_id))
+        .collect();
+    Python::with_gil(|py| {
+        let message = format!(
+            "{} tax IDs are deprecated: {}",
+            resolved.len(),
+            pairs.join(", ")
+        );
+        let category = py.import("builtins")?.getattr("DeprecationWarning")?;
+        PyErr::warn(py, category, &message, 1)
+    })
+}
+
+/// Tell the caller that a former name of a taxon was resolved, like warn_deprecated() for IDs
+fn warn_deprecated_name(old_name: &str, new_name: &str) -> PyResult<()> {
+    logging::debug(&format!(
+        "Resolved former name {} to {}",
+        old_name, new_name
+    ));
+    Python::with_gil(|py| {
+        let message = format!("Name {} is deprecated, use {} instead", old_name, new_name);
+        let category = py.import("builtins")?.getattr("DeprecationWarning")?;
+        PyErr::warn(py, category, &message, 1)
+    })
+}
+
+/// Run a method of cache in the default executor of the running asyncio event loop
+///
+/// The methods release the GIL for the actual work, so the event loop keeps running meanwhile.
+fn run_in_executor(
+    py: Python,
+    cache: PyObject,
+    method: &str,
+    args: &PyTuple,
+    kwargs: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let event_loop = py.import("asyncio")?.getattr("get_running_loop")?.call0()?;
+    // run_in_executor() doesn't pass on keyword arguments
+    let method = cache.getattr(py, method)?.into_ref(py);
+    let call = py.import("functools")?.getattr("partial")?.call(
+        PyTuple::new(
+            py,
+            std::iter::once(method)
+                .chain(args.iter())
+                .collect::<Vec<_>>(),
+        ),
+        kwargs,
+    )?;
+    let future = event_loop.call_method1("run_in_executor", (py.None(), call))?;
+    Ok(future.into())
+}
+
+/// Get a path from a str or any os.PathLike object
+fn extract_path(path: &PyAny) -> PyResult<PathBuf> {
+    let os = path.py().import("os")?;
+    os.getattr("fspath")?.call1((path,))?.extract()
+}
+
+fn parse_mapping_rules(
+    rules: Vec<MappingRuleSpec>,
+    aliases: &Aliases,
+) -> Result<Vec<MappingRule>, PyMibigTaxonError> {
+    let mut parsed = Vec::with_capacity(rules.len());
+    for (rank, name, target) in rules {
+        let rank = aliases.rank(&rank).to_string();
+        if !RANKS.contains(&rank.as_str()) {
+            return Err(PyMibigTaxonError::InvalidRank(rank));
+        }
+        parsed.push(MappingRule { rank, name, target });
+    }
+    Ok(parsed)
+}
+
+/// Ranks stored on an entry, from most to least specific
+const RANKS: [&str; 8] = [
+    "species",
+    "genus",
+    "family",
+    "order",
+    "class",
+    "phylum",
+    "kingdom",
+    "superkingdom",
+];
+
+fn get_rank_from_entry<'a>(entry: &EntryRef<'a>, rank: &str) -> Option<&'a str> {
+    match rank {
+        "species" => Some(entry.species),
+        "genus" => Some(entry.genus),
+        "family" => Some(entry.family),
+        "order" => Some(entry.order),
+        "class" => Some(entry.class),
+        "phylum" => Some(entry.phylum),
+        "kingdom" => Some(entry.kingdom),
+        "superkingdom" => Some(entry.superkingdom),
+        _ => None,
+    }
+}
+
+/// Tax ID of the "Viruses" node, the acellular root of all virus realms
+const VIRUSES_TAX_ID: i64 = 10239;
+
+/// Virus realms, listed in place of a superkingdom by newer taxdumps
+const VIRUS_REALMS: [&str; 6] = [
+    "Adnaviria",
+    "Duplodnaviria",
+    "Monodnaviria",
+    "Riboviria",
+    "Ribozyviria",
+    "Varidnaviria",
+];
+
+fn get_taxon_from_entry(entry: &EntryRef) -> PyResult<String> {
+    Ok(match_taxon(entry)?.taxon)
+}
+
+/// The antiSMASH taxon of an entry and the lineage rank and name it's based on
+struct TaxonMatch {
+    taxon: String,
+    rank: String,
+    name: String,
+    /// Whether a custom mapping rule matched
+    by_rule: bool,
+}
+
+/// Built-in mapping of entries to antiSMASH taxa
+fn match_taxon(entry: &EntryRef) -> Result<TaxonMatch, PyMibigTaxonError> {
+    let found = |taxon: &str, rank: &str, name: &str| {
+        Ok(TaxonMatch {
+            taxon: taxon.to_string(),
+            rank: rank.to_string(),
+            name: name.to_string(),
+            by_rule: false,
+        })
+    };
+    let unmappable = |rank: &str, name: &str| {
+        Err(PyMibigTaxonError::InvalidAntismashTaxon {
+            rank: rank.to_string(),
+            name: name.to_string(),
+        })
+    };
+    let superkingdom = entry.superkingdom;
+    match superkingdom {
+        "Archaea" | "Bacteria" => found("bacteria", "superkingdom", superkingdom),
+        "Viruses" => found("viruses", "superkingdom", superkingdom),
+        realm if VIRUS_REALMS.contains(&realm) => found("viruses", "superkingdom", realm),
+        "Eukaryota" => match entry.kingdom {
+            "Fungi" => found("fungi", "kingdom", entry.kingdom),
+            "Viridiplantae" => found("plants", "kingdom", entry.kingdom),
+            "Unknown" => match entry.phylum {
+                "Rhodophyta" | "Bacillariophyta" => found("plants", "phylum", entry.phylum),
+                "Unknown" => match entry.class {
+                    "Dinophyceae" => found("plants", "class", entry.class),
+                    _ => unmappable("class", entry.class),
+                },
+                _ => unmappable("phylum", entry.phylum),
+            },
+            _ => unmappable("kingdom", entry.kingdom),
+        },
+        // Many metagenomes are superkingdom "Unknown" but still bacterial
+        _ => found("bacteria", "superkingdom", superkingdom),
+    }
+}
+
+/// Get the version of the cache file format written by TaxonCache.save()
+#[pyfunction]
+fn cache_format_version() -> u32 {
+    storage::FORMAT_VERSION
 }
 
 #[pymodule]
-fn mibig_taxa(_py: Python, m: &PyModule) -> PyResult<()> {
+fn mibig_taxa(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(cache_format_version, m)?)?;
+    cli::register(m)?;
     m.add_class::<PyTaxonCache>()?;
     m.add_class::<PyTaxonEntry>()?;
+    m.add_class::<PyMappedTaxonCache>()?;
+    m.add_class::<PyValidationReport>()?;
+    m.add_class::<PyCacheDiff>()?;
+    m.add_class::<PyClassification>()?;
+    m.add_class::<PyTaxonTabulation>()?;
+    m.add("MibigTaxaError", py.get_type::<MibigTaxaError>())?;
+    m.add("TaxonNotFoundError", py.get_type::<TaxonNotFoundError>())?;
+    m.add(
+        "InvalidAntismashTaxonError",
+        py.get_type::<InvalidAntismashTaxonError>(),
+    )?;
+    m.add("AmbiguousNameError", py.get_type::<AmbiguousNameError>())?;
+    m.add(
+        "InvalidArgumentError",
+        py.get_type::<InvalidArgumentError>(),
+    )?;
+    m.add("DataFileError", py.get_type::<DataFileError>())?;
+    m.add(
+        "CacheVersionMismatch",
+        py.get_type::<CacheVersionMismatch>(),
+    )?;
     Ok(())
 }
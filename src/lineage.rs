@@ -0,0 +1,196 @@
+//! Lineage strings for reports and GenBank-like files, and checks on the names in a lineage
+
+use crate::errors::PyMibigTaxonError;
+use crate::get_rank_from_entry;
+use crate::interned::EntryRef;
+
+/// Ranks of a lineage string by default, from most to least general
+pub(crate) const LINEAGE_RANKS: [&str; 7] = [
+    "superkingdom",
+    "phylum",
+    "class",
+    "order",
+    "family",
+    "genus",
+    "species",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LineageStyle {
+    /// "Bacteria; Actinomycetota; Actinomycetes", leaving out unknown ranks
+    Ncbi,
+    /// "d__Bacteria;p__Actinomycetota;c__Actinomycetes", keeping empty unknown ranks
+    Gtdb,
+}
+
+impl LineageStyle {
+    pub fn parse(value: &str) -> Result<LineageStyle, PyMibigTaxonError> {
+        match value {
+            "ncbi" => Ok(LineageStyle::Ncbi),
+            "gtdb" => Ok(LineageStyle::Gtdb),
+            _ => Err(PyMibigTaxonError::InvalidOption {
+                option: "style",
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    pub fn default_delimiter(self) -> &'static str {
+        match self {
+            LineageStyle::Ncbi => "; ",
+            LineageStyle::Gtdb => ";",
+        }
+    }
+}
+
+/// Join the names of ranks of entry into a lineage string
+///
+/// The ranks need to be valid entry ranks, with aliases resolved for own_rank too. The taxdump
+/// doesn't list a taxon in its own lineage, so the name of the entry is used for its own rank, if
+/// known. Species are given by their binomial name, see species_name().
+pub(crate) fn format(
+    entry: &EntryRef,
+    own_rank: Option<&str>,
+    ranks: &[String],
+    style: LineageStyle,
+    delimiter: &str,
+) -> String {
+    let mut parts = Vec::with_capacity(ranks.len());
+    for rank in ranks {
+        let name = match get_rank_from_entry(entry, rank) {
+            Some(name) if is_known(name) && rank == "species" => species_name(entry, name),
+            Some(name) if is_known(name) => name.to_string(),
+            _ if own_rank == Some(rank.as_str()) => entry.name.to_string(),
+            _ => String::new(),
+        };
+        match style {
+            LineageStyle::Ncbi if name.is_empty() => {}
+            LineageStyle::Ncbi => parts.push(name),
+            LineageStyle::Gtdb => parts.push(format!("{}__{}", gtdb_prefix(rank), name)),
+        }
+    }
+    parts.join(delimiter)
+}
+
+fn is_known(name: &str) -> bool {
+    !name.is_empty() && name != "Unknown"
+}
+
+/// The binomial name of the species of an entry
+///
+/// Entries built from a taxdump only store the epithet of the species, e.g. "coelicolor" for
+/// Streptomyces coelicolor, so this adds the genus in front of it.
+fn species_name(entry: &EntryRef, species: &str) -> String {
+    if species.contains(' ') || !is_known(entry.genus) {
+        species.to_string()
+    } else {
+        format!("{} {}", entry.genus, species)
+    }
+}
+
+fn gtdb_prefix(rank: &str) -> &'static str {
+    match rank {
+        // GTDB calls the top level a domain
+        "superkingdom" => "d",
+        "kingdom" => "k",
+        "phylum" => "p",
+        "class" => "c",
+        "order" => "o",
+        "family" => "f",
+        "genus" => "g",
+        _ => "s",
+    }
+}
+
+/// Whether a taxon name marks environmental or metagenome samples
+///
+/// NCBI files uncultured organisms under "environmental samples" nodes, e.g. "uncultured bacterium"
+/// under "bacteria environmental samples", and metagenomes under "metagenomes".
+pub(crate) fn is_environmental_name(name: &str) -> bool {
+    name == "environmental samples"
+        || name.ends_with(" environmental samples")
+        || name == "metagenomes"
+        || name.starts_with("uncultured ")
+}
+
+/// Whether a taxon name marks a placeholder for taxa without a proper classification
+///
+/// e.g. "unclassified Streptomyces", "unclassified sequences" or "Actinomycetes incertae sedis"
+pub(crate) fn is_unclassified_name(name: &str) -> bool {
+    name.starts_with("unclassified ") || name.ends_with(" incertae sedis")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranks() -> Vec<String> {
+        LINEAGE_RANKS.iter().map(|rank| rank.to_string()).collect()
+    }
+
+    fn streptomyces_entry<'a>(tax_id: i64, name: &'a str, species: &'a str) -> EntryRef<'a> {
+        EntryRef {
+            tax_id,
+            name,
+            species,
+            genus: "Streptomyces",
+            family: "Streptomycetaceae",
+            order: "Kitasatosporales",
+            class: "Actinomycetes",
+            phylum: "Actinomycetota",
+            kingdom: "Unknown",
+            superkingdom: "Bacteria",
+        }
+    }
+
+    #[test]
+    fn species_entry() {
+        // The taxdump lists no species for a species itself
+        let entry = streptomyces_entry(1902, "Streptomyces coelicolor", "Unknown");
+        let ranks = ranks();
+        assert_eq!(
+            format(&entry, Some("species"), &ranks, LineageStyle::Ncbi, "; "),
+            "Bacteria; Actinomycetota; Actinomycetes; Kitasatosporales; Streptomycetaceae; \
+             Streptomyces; Streptomyces coelicolor"
+        );
+        assert_eq!(
+            format(&entry, Some("species"), &ranks, LineageStyle::Gtdb, ";"),
+            "d__Bacteria;p__Actinomycetota;c__Actinomycetes;o__Kitasatosporales;\
+             f__Streptomycetaceae;g__Streptomyces;s__Streptomyces coelicolor"
+        );
+    }
+
+    #[test]
+    fn strain_entry() {
+        let entry = streptomyces_entry(100226, "Streptomyces coelicolor A3(2)", "coelicolor");
+        let ranks = ranks();
+        assert_eq!(
+            format(&entry, Some("strain"), &ranks, LineageStyle::Ncbi, "; "),
+            "Bacteria; Actinomycetota; Actinomycetes; Kitasatosporales; Streptomycetaceae; \
+             Streptomyces; Streptomyces coelicolor"
+        );
+        let species = ["species".to_string()];
+        assert_eq!(
+            format(&entry, Some("strain"), &species, LineageStyle::Gtdb, ";"),
+            "s__Streptomyces coelicolor"
+        );
+    }
+
+    #[test]
+    fn unknown_ranks() {
+        let entry = EntryRef {
+            genus: "Unknown",
+            family: "Unknown",
+            ..streptomyces_entry(1931, "Streptomyces sp.", "sp.")
+        };
+        let ranks = ranks();
+        assert_eq!(
+            format(&entry, None, &ranks, LineageStyle::Ncbi, "; "),
+            "Bacteria; Actinomycetota; Actinomycetes; Kitasatosporales; sp."
+        );
+        assert_eq!(
+            format(&entry, None, &ranks, LineageStyle::Gtdb, ";"),
+            "d__Bacteria;p__Actinomycetota;c__Actinomycetes;o__Kitasatosporales;f__;g__;s__sp."
+        );
+    }
+}
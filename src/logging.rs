@@ -0,0 +1,55 @@
+//! Records for the `mibig_taxa` logger of python's logging module
+//!
+//! Like the progress callbacks, records can be emitted while the GIL is released, it's taken again
+//! for each record. Configure the logger as usual, e.g. with `logging.basicConfig()`.
+
+use pyo3::prelude::*;
+
+const LOGGER_NAME: &str = "mibig_taxa";
+
+/// Levels of python's logging module
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Level {
+    Debug = 10,
+    Info = 20,
+    Warning = 30,
+}
+
+/// Whether the mibig_taxa logger handles records of level, to skip building unused messages
+pub(crate) fn enabled(level: Level) -> bool {
+    Python::with_gil(|py| {
+        logger(py)
+            .and_then(|logger| {
+                logger
+                    .call_method1("isEnabledFor", (level as i32,))?
+                    .is_true()
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// Emit a record to the mibig_taxa logger
+///
+/// Failing to log doesn't fail the operation that is logged, so errors are dropped.
+pub(crate) fn log(level: Level, message: &str) {
+    Python::with_gil(|py| {
+        let _ = logger(py).and_then(|logger| logger.call_method1("log", (level as i32, message)));
+    })
+}
+
+pub(crate) fn debug(message: &str) {
+    log(Level::Debug, message)
+}
+
+pub(crate) fn info(message: &str) {
+    log(Level::Info, message)
+}
+
+pub(crate) fn warning(message: &str) {
+    log(Level::Warning, message)
+}
+
+fn logger(py: Python<'_>) -> PyResult<&PyAny> {
+    py.import("logging")?
+        .call_method1("getLogger", (LOGGER_NAME,))
+}
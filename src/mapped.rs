@@ -0,0 +1,439 @@
+//! Memory-mapped read-only cache files
+//!
+//! The file layout is designed to be used in place, without deserialising:
+//!
+//! * header: magic, format version, number of entries, number of deprecated IDs
+//! * entry records sorted by tax ID: tax ID followed by (offset, length) pairs into the string area
+//! * deprecated ID records sorted by old ID: old ID, new ID
+//! * string area holding each distinct name once as UTF-8, shared by all entries using it
+//!
+//! All numbers are stored little-endian.
+//!
+//! Files are mapped shared, so changing a file in place while it's mapped breaks all processes
+//! using it. save_path() writes a new file and renames it over the old one, which leaves existing
+//! mappings intact, and files copied into place should be replaced the same way.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use mibig_taxa::{MibigTaxonError, NcbiTaxEntry};
+
+use crate::errors::PyMibigTaxonError;
+use crate::interned::{EntryStore, FIELD_COUNT};
+
+const MAGIC: &[u8; 8] = b"MIBIGTXM";
+const FORMAT_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 24;
+const ENTRY_SIZE: usize = 8 + FIELD_COUNT * 8;
+const DEPRECATED_SIZE: usize = 16;
+
+pub(crate) fn save_path(cache: &EntryStore, outfile: &Path) -> Result<usize, MibigTaxonError> {
+    let mut tax_ids: Vec<i64> = cache.tax_ids().collect();
+    tax_ids.sort_unstable();
+    let mut deprecated_ids: Vec<(i64, i64)> = cache
+        .deprecated_ids
+        .iter()
+        .map(|(old_id, new_id)| (*old_id, *new_id))
+        .collect();
+    deprecated_ids.sort_unstable();
+
+    let mut records = Vec::with_capacity(tax_ids.len() * ENTRY_SIZE);
+    let mut strings: Vec<u8> = Vec::new();
+    let mut offsets: HashMap<&str, u32> = HashMap::new();
+    for tax_id in &tax_ids {
+        records.extend_from_slice(&tax_id.to_le_bytes());
+        for field in cache.entry(*tax_id).fields() {
+            let offset = *offsets.entry(field).or_insert_with(|| {
+                strings.extend_from_slice(field.as_bytes());
+                (strings.len() - field.len()) as u32
+            });
+            records.extend_from_slice(&offset.to_le_bytes());
+            records.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        }
+    }
+    if strings.len() > u32::MAX as usize {
+        let err = io::Error::new(
+            io::ErrorKind::InvalidData,
+            "cache too large for mmap format",
+        );
+        return Err(MibigTaxonError::from(err));
+    }
+
+    // Processes with the old file mapped keep using it when the new one is renamed over it
+    let mut temp_name = outfile.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = outfile.with_file_name(temp_name);
+    let result = write_file(&temp_path, &tax_ids, &deprecated_ids, &records, &strings)
+        .and_then(|_| fs::rename(&temp_path, outfile));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(MibigTaxonError::from(err));
+    }
+
+    Ok(tax_ids.len())
+}
+
+fn write_file(
+    path: &Path,
+    tax_ids: &[i64],
+    deprecated_ids: &[(i64, i64)],
+    records: &[u8],
+    strings: &[u8],
+) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    out.write_all(&(tax_ids.len() as u32).to_le_bytes())?;
+    out.write_all(&(deprecated_ids.len() as u32).to_le_bytes())?;
+    out.write_all(&0u32.to_le_bytes())?;
+    out.write_all(records)?;
+    for (old_id, new_id) in deprecated_ids {
+        out.write_all(&old_id.to_le_bytes())?;
+        out.write_all(&new_id.to_le_bytes())?;
+    }
+    out.write_all(strings)?;
+    out.flush()
+}
+
+/// Read-only view of a cache file in the mmap format
+pub(crate) struct MappedCache {
+    map: Mmap,
+    entry_count: usize,
+    deprecated_count: usize,
+    deprecated_start: usize,
+    strings_start: usize,
+}
+
+impl MappedCache {
+    pub fn open(path: &Path) -> Result<MappedCache, PyMibigTaxonError> {
+        let map = Mmap::open(path).map_err(MibigTaxonError::from)?;
+        let data = map.as_slice();
+
+        if data.len() < HEADER_SIZE || &data[..8] != MAGIC {
+            return Err(PyMibigTaxonError::InvalidCacheFile(
+                "not an mmap cache file".to_string(),
+            ));
+        }
+        let version = read_u32(data, 8);
+        if version != FORMAT_VERSION {
+            return Err(PyMibigTaxonError::CacheVersionMismatch {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        let entry_count = read_u32(data, 12) as usize;
+        let deprecated_count = read_u32(data, 16) as usize;
+        // The counts come from the file, so they can overflow the sizes on 32-bit platforms
+        let deprecated_start = entry_count
+            .checked_mul(ENTRY_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE));
+        let strings_start = deprecated_count
+            .checked_mul(DEPRECATED_SIZE)
+            .zip(deprecated_start)
+            .and_then(|(size, start)| size.checked_add(start));
+        let (deprecated_start, strings_start) = match (deprecated_start, strings_start) {
+            (Some(deprecated_start), Some(strings_start)) if strings_start <= data.len() => {
+                (deprecated_start, strings_start)
+            }
+            _ => {
+                return Err(PyMibigTaxonError::InvalidCacheFile(
+                    "file is truncated".to_string(),
+                ))
+            }
+        };
+
+        Ok(MappedCache {
+            map,
+            entry_count,
+            deprecated_count,
+            deprecated_start,
+            strings_start,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entry_count
+    }
+
+    pub fn contains(&self, tax_id: i64) -> bool {
+        self.find_entry(tax_id).is_some()
+    }
+
+    pub fn get(&self, tax_id: i64) -> Option<NcbiTaxEntry> {
+        let record = self.find_entry(tax_id)?;
+        Some(NcbiTaxEntry {
+            tax_id,
+            name: self.field(record, 0).into_owned(),
+            species: self.field(record, 1).into_owned(),
+            genus: self.field(record, 2).into_owned(),
+            family: self.field(record, 3).into_owned(),
+            order: self.field(record, 4).into_owned(),
+            class: self.field(record, 5).into_owned(),
+            phylum: self.field(record, 6).into_owned(),
+            kingdom: self.field(record, 7).into_owned(),
+            superkingdom: self.field(record, 8).into_owned(),
+        })
+    }
+
+    pub fn get_name(&self, tax_id: i64) -> Option<String> {
+        let record = self.find_entry(tax_id)?;
+        Some(self.field(record, 0).into_owned())
+    }
+
+    /// Get the ID a deprecated ID was merged into
+    pub fn get_deprecated(&self, tax_id: i64) -> Option<i64> {
+        let data = self.map.as_slice();
+        let start = self.deprecated_start;
+        let index = binary_search(
+            self.deprecated_count,
+            |i| read_i64(data, start + i * DEPRECATED_SIZE),
+            tax_id,
+        )?;
+        Some(read_i64(data, start + index * DEPRECATED_SIZE + 8))
+    }
+
+    fn find_entry(&self, tax_id: i64) -> Option<usize> {
+        let data = self.map.as_slice();
+        let index = binary_search(
+            self.entry_count,
+            |i| read_i64(data, HEADER_SIZE + i * ENTRY_SIZE),
+            tax_id,
+        )?;
+        Some(HEADER_SIZE + index * ENTRY_SIZE)
+    }
+
+    fn field(&self, record: usize, field: usize) -> Cow<'_, str> {
+        let data = self.map.as_slice();
+        let offset = read_u32(data, record + 8 + field * 8) as usize;
+        let length = read_u32(data, record + 12 + field * 8) as usize;
+
+        // A damaged file shouldn't crash the interpreter, so fall back to an empty name
+        let start = self.strings_start.checked_add(offset);
+        let end = start.and_then(|start| start.checked_add(length));
+        match start.zip(end).and_then(|(start, end)| data.get(start..end)) {
+            Some(bytes) => String::from_utf8_lossy(bytes),
+            None => Cow::Borrowed(""),
+        }
+    }
+}
+
+fn binary_search(count: usize, key_at: impl Fn(usize) -> i64, key: i64) -> Option<usize> {
+    let mut low = 0;
+    let mut high = count;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match key_at(mid).cmp(&key) {
+            Ordering::Equal => return Some(mid),
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+        }
+    }
+    None
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(unix)]
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// The mapping is read-only and never changes after creation
+#[cfg(unix)]
+unsafe impl Send for Mmap {}
+#[cfg(unix)]
+unsafe impl Sync for Mmap {}
+
+#[cfg(unix)]
+impl Mmap {
+    fn open(path: &Path) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = fs::File::open(path)?;
+        let len: usize = file
+            .metadata()?
+            .len()
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "file too large to map"))?;
+        if len == 0 {
+            // mmap() refuses empty mappings
+            return Ok(Mmap {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+/// Platforms without mmap() just read the whole file
+#[cfg(not(unix))]
+struct Mmap {
+    data: Vec<u8>,
+}
+
+#[cfg(not(unix))]
+impl Mmap {
+    fn open(path: &Path) -> io::Result<Mmap> {
+        Ok(Mmap {
+            data: fs::read(path)?,
+        })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interned::EntryRef;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("mibig_taxa_{}_{}", std::process::id(), name))
+    }
+
+    fn example_cache() -> EntryStore {
+        let mut cache = EntryStore::default();
+        for (tax_id, name, species) in [
+            (
+                100226,
+                "Streptomyces coelicolor A3(2)",
+                "Streptomyces coelicolor",
+            ),
+            (1883, "Streptomyces", "Unknown"),
+            (1902, "Streptomyces coelicolor", "Streptomyces coelicolor"),
+        ] {
+            cache.insert(EntryRef {
+                tax_id,
+                name,
+                species,
+                genus: "Streptomyces",
+                family: "Streptomycetaceae",
+                order: "Kitasatosporales",
+                class: "Actinomycetes",
+                phylum: "Actinomycetota",
+                kingdom: "Unknown",
+                superkingdom: "Bacteria",
+            });
+        }
+        cache.deprecated_ids.insert(12345, 100226);
+        cache.deprecated_ids.insert(99999, 1883);
+        cache
+    }
+
+    #[test]
+    fn save_and_open() {
+        let cache = example_cache();
+        let path = temp_path("save_and_open.mmap");
+        assert_eq!(save_path(&cache, &path).unwrap(), 3);
+        let mapped = MappedCache::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mapped.len(), cache.len());
+        for entry in cache.iter() {
+            let mapped_entry = mapped.get(entry.tax_id).unwrap();
+            assert_eq!(EntryRef::from(&mapped_entry), entry);
+            assert_eq!(mapped.get_name(entry.tax_id).as_deref(), Some(entry.name));
+        }
+        assert!(!mapped.contains(1));
+        assert!(mapped.get(1).is_none());
+        assert_eq!(mapped.get_deprecated(12345), Some(100226));
+        assert_eq!(mapped.get_deprecated(99999), Some(1883));
+        assert_eq!(mapped.get_deprecated(1883), None);
+    }
+
+    #[test]
+    fn replace_while_open() {
+        let path = temp_path("replace_while_open.mmap");
+        save_path(&example_cache(), &path).unwrap();
+        let mapped = MappedCache::open(&path).unwrap();
+
+        save_path(&EntryStore::default(), &path).unwrap();
+        let replaced = MappedCache::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // The old mapping still sees the old file
+        assert_eq!(mapped.len(), 3);
+        assert_eq!(mapped.get_name(1883).as_deref(), Some("Streptomyces"));
+        assert_eq!(replaced.len(), 0);
+    }
+
+    #[test]
+    fn truncated_file() {
+        let path = temp_path("truncated_file.mmap");
+        save_path(&example_cache(), &path).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..HEADER_SIZE + ENTRY_SIZE]).unwrap();
+        let result = MappedCache::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(PyMibigTaxonError::InvalidCacheFile(_))
+        ));
+    }
+
+    #[test]
+    fn huge_counts() {
+        let path = temp_path("huge_counts.mmap");
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        fs::write(&path, &data).unwrap();
+        let result = MappedCache::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(PyMibigTaxonError::InvalidCacheFile(_))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+//! Combining caches built from different MIBiG data snapshots
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::errors::PyMibigTaxonError;
+use crate::interned::EntryStore;
+use crate::storage::CacheExtras;
+use crate::taxdump::TaxonNode;
+
+/// Which cache wins if both have different data for the same tax ID
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MergePolicy {
+    /// The cache being merged in is the newer snapshot and wins
+    Newer,
+    /// The existing cache wins
+    Older,
+    /// Refuse to merge conflicting caches
+    Error,
+}
+
+impl MergePolicy {
+    pub fn parse(value: &str) -> Result<MergePolicy, PyMibigTaxonError> {
+        match value {
+            "newer" => Ok(MergePolicy::Newer),
+            "older" => Ok(MergePolicy::Older),
+            "error" => Ok(MergePolicy::Error),
+            _ => Err(PyMibigTaxonError::InvalidOption {
+                option: "prefer",
+                value: value.to_string(),
+            }),
+        }
+    }
+}
+
+/// Merge other into cache
+///
+/// An ID that is a current entry in one cache and deprecated in the other counts as a conflict,
+/// as does an ID with different entries or merge targets. Nothing is changed if merging fails.
+pub(crate) fn merge(
+    cache: &mut EntryStore,
+    extras: &mut CacheExtras,
+    other: &EntryStore,
+    other_extras: &CacheExtras,
+    policy: MergePolicy,
+) -> Result<(), PyMibigTaxonError> {
+    if policy == MergePolicy::Error {
+        if let Some(tax_id) = find_conflicts(cache, extras, other, other_extras)
+            .iter()
+            .next()
+        {
+            return Err(PyMibigTaxonError::MergeConflict(*tax_id));
+        }
+    }
+
+    for entry in other.iter() {
+        let tax_id = &entry.tax_id;
+        if policy == MergePolicy::Older && cache.deprecated_ids.contains_key(tax_id) {
+            continue;
+        }
+        cache.deprecated_ids.remove(tax_id);
+        if policy != MergePolicy::Older || !cache.contains(*tax_id) {
+            // The manual marker belongs to the entry that ends up in the cache
+            if other_extras.manual.contains(tax_id) {
+                extras.manual.insert(*tax_id);
+            } else {
+                extras.manual.remove(tax_id);
+            }
+            cache.insert(entry);
+        }
+        if let Some(node) = other_extras.nodes.get(tax_id) {
+            insert(&mut extras.nodes, *tax_id, node, policy);
+        }
+        if let Some(names) = other_extras.names.get(tax_id) {
+            insert(&mut extras.names, *tax_id, names, policy);
+        }
+    }
+
+    for (old_id, new_id) in &other.deprecated_ids {
+        if policy == MergePolicy::Older && cache.contains(*old_id) {
+            continue;
+        }
+        cache.remove(*old_id);
+        extras.nodes.remove(old_id);
+        extras.names.remove(old_id);
+        extras.manual.remove(old_id);
+        insert(&mut cache.deprecated_ids, *old_id, new_id, policy);
+    }
+
+    // Divisions and genetic codes are reference tables, not part of the conflict check
+    for (division_id, division) in &other_extras.divisions {
+        insert(&mut extras.divisions, *division_id, division, policy);
+    }
+    for (code_id, name) in &other_extras.genetic_codes {
+        insert(&mut extras.genetic_codes, *code_id, name, policy);
+    }
+
+    if extras.taxdump_date.is_none() || policy == MergePolicy::Newer {
+        if let Some(taxdump_date) = &other_extras.taxdump_date {
+            extras.taxdump_date = Some(taxdump_date.clone());
+        }
+    }
+    if extras.metadata.is_none() || policy == MergePolicy::Newer {
+        if let Some(metadata) = &other_extras.metadata {
+            extras.metadata = Some(metadata.clone());
+        }
+    }
+
+    Ok(())
+}
+
+fn insert<T: Clone>(map: &mut HashMap<i64, T>, tax_id: i64, value: &T, policy: MergePolicy) {
+    if policy == MergePolicy::Older {
+        map.entry(tax_id).or_insert_with(|| value.clone());
+    } else {
+        map.insert(tax_id, value.clone());
+    }
+}
+
+/// Get the tax IDs the two caches disagree on, sorted
+fn find_conflicts(
+    cache: &EntryStore,
+    extras: &CacheExtras,
+    other: &EntryStore,
+    other_extras: &CacheExtras,
+) -> BTreeSet<i64> {
+    let mut conflicts = BTreeSet::new();
+    for entry in other.iter() {
+        let tax_id = &entry.tax_id;
+        let differs = match cache.get(*tax_id) {
+            Some(existing) => existing != entry,
+            None => cache.deprecated_ids.contains_key(tax_id),
+        };
+        let node_differs = matches!(
+            (extras.nodes.get(tax_id), other_extras.nodes.get(tax_id)),
+            (Some(existing), Some(node)) if !same_node(existing, node)
+        );
+        if differs || node_differs {
+            conflicts.insert(*tax_id);
+        }
+    }
+    for (old_id, new_id) in &other.deprecated_ids {
+        let differs = match cache.deprecated_ids.get(old_id) {
+            Some(existing) => existing != new_id,
+            None => cache.contains(*old_id),
+        };
+        if differs {
+            conflicts.insert(*old_id);
+        }
+    }
+    conflicts
+}
+
+/// Only the tree structure counts, so caches built before divisions and genetic codes were read
+/// don't conflict with newer ones
+fn same_node(a: &TaxonNode, b: &TaxonNode) -> bool {
+    a.parent_id == b.parent_id && a.rank == b.rank
+}
@@ -0,0 +1,56 @@
+//! What lookups do with tax IDs that aren't in the cache
+//!
+//! By default lookups raise a TaxonNotFoundError. For exploratory work on large batches, missing
+//! IDs can map to None or be left out instead.
+
+use pyo3::prelude::*;
+
+use crate::errors::{PyMibigTaxonError, TaxonNotFoundError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum OnMissing {
+    /// Raise a TaxonNotFoundError
+    Raise,
+    /// Return None in place of the result
+    None,
+    /// Leave the ID out of batch results
+    Skip,
+}
+
+impl OnMissing {
+    pub fn parse(value: &str) -> Result<OnMissing, PyMibigTaxonError> {
+        match value {
+            "raise" => Ok(OnMissing::Raise),
+            "none" => Ok(OnMissing::None),
+            "skip" => Ok(OnMissing::Skip),
+            _ => Err(PyMibigTaxonError::InvalidOption {
+                option: "on_missing",
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    /// Parse the option of a lookup of a single ID, where there is nothing to skip
+    pub fn parse_single(value: &str) -> Result<OnMissing, PyMibigTaxonError> {
+        match OnMissing::parse(value)? {
+            OnMissing::Skip => Err(PyMibigTaxonError::InvalidOption {
+                option: "on_missing",
+                value: value.to_string(),
+            }),
+            on_missing => Ok(on_missing),
+        }
+    }
+
+    /// Turn the TaxonNotFoundError of a single lookup into None, unless it should be raised
+    ///
+    /// Other errors, e.g. for entries without an antiSMASH taxon, are raised either way.
+    pub fn handle<T>(self, py: Python, result: PyResult<T>) -> PyResult<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if self != OnMissing::Raise && err.is_instance::<TaxonNotFoundError>(py) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Progress reporting to python callbacks while the GIL is released
+
+use std::io::{self, Read};
+use std::sync::Mutex;
+
+use pyo3::prelude::*;
+
+/// Calls an optional python callback with (stage, done, total)
+pub(crate) struct Progress {
+    callback: Option<PyObject>,
+    error: Mutex<Option<PyErr>>,
+}
+
+impl Progress {
+    pub fn new(callback: Option<PyObject>) -> Progress {
+        Progress {
+            callback,
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Report progress, failing if the callback raised an exception
+    pub fn report(&self, stage: &str, done: u64, total: u64) -> io::Result<()> {
+        let callback = match &self.callback {
+            Some(callback) => callback,
+            None => return Ok(()),
+        };
+
+        let result = Python::with_gil(|py| callback.call1(py, (stage, done, total)).map(|_| ()));
+        if let Err(err) = result {
+            *self.error.lock().unwrap() = Some(err);
+            return Err(io::Error::other("progress callback failed"));
+        }
+        Ok(())
+    }
+
+    /// Wrap a reader to report the number of bytes read so far
+    pub fn wrap<R: Read>(
+        &self,
+        stage: &'static str,
+        reader: R,
+        total: u64,
+    ) -> ProgressReader<'_, R> {
+        ProgressReader {
+            inner: reader,
+            progress: self,
+            stage,
+            done: 0,
+            total,
+            last_percent: None,
+        }
+    }
+
+    /// Get the exception raised by the callback, if any
+    pub fn take_error(&self) -> Option<PyErr> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+    stage: &'static str,
+    done: u64,
+    total: u64,
+    last_percent: Option<u64>,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.done += size as u64;
+
+        // Only call back into python once per percent, and once when done
+        let percent = if size == 0 || self.total == 0 {
+            100
+        } else {
+            (self.done * 100 / self.total).min(100)
+        };
+        if self.last_percent != Some(percent) {
+            self.last_percent = Some(percent);
+            self.progress
+                .report(self.stage, self.done, self.total.max(self.done))?;
+        }
+        Ok(size)
+    }
+}
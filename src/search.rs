@@ -0,0 +1,117 @@
+//! Prefix and fuzzy name search
+
+use std::cmp::Ordering;
+
+/// Fuzzy matches scoring lower than this are not reported
+const MIN_FUZZY_SCORE: f64 = 0.75;
+
+/// A lowercased search query, with the pieces used to rule out fuzzy matches cheaply
+pub(crate) struct NameQuery {
+    chars: Vec<char>,
+    /// For each maximum edit distance d, the query split into d + 1 pieces
+    ///
+    /// Each edit changes at most one piece, so names within d edits contain at least one of the
+    /// pieces unchanged. Checking that is much cheaper than the edit distance.
+    pieces: Vec<Vec<String>>,
+}
+
+impl NameQuery {
+    pub fn new(query: &str) -> NameQuery {
+        let chars: Vec<char> = query.to_lowercase().chars().collect();
+        // Names can be longer than the query, which allows up to a third of its length in edits
+        let max_distance = chars.len() / 3;
+        let pieces = (0..=max_distance)
+            .map(|distance| {
+                let count = distance + 1;
+                (0..count)
+                    .map(|i| {
+                        let start = i * chars.len() / count;
+                        let end = (i + 1) * chars.len() / count;
+                        chars[start..end].iter().collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        NameQuery { chars, pieces }
+    }
+
+    /// Score how well a lowercased name matches the query
+    ///
+    /// Exact matches score 1.0, prefix matches score between 0.5 and 1.0 depending on how much of
+    /// the name the query covers, and fuzzy matches score by their normalised edit distance.
+    pub fn score(&self, name: &str, fuzzy: bool) -> Option<f64> {
+        let query = &self.chars;
+        let name_length = name.chars().count();
+        let is_prefix = name_length >= query.len() && name.chars().zip(query).all(|(a, b)| a == *b);
+        if is_prefix && name_length == query.len() {
+            return Some(1.0);
+        }
+
+        let mut best: Option<f64> = None;
+        if is_prefix && !query.is_empty() {
+            best = Some(0.5 + 0.5 * query.len() as f64 / name_length as f64);
+        }
+
+        if fuzzy && self.may_be_within(name, name_length) {
+            let longest = query.len().max(name_length);
+            let max_distance = max_distance(longest);
+            let name: Vec<char> = name.chars().collect();
+            if let Some(distance) = bounded_levenshtein(query, &name, max_distance) {
+                let score = 1.0 - distance as f64 / longest as f64;
+                if score > best.unwrap_or(0.0) {
+                    best = Some(score);
+                }
+            }
+        }
+        best
+    }
+
+    /// Whether name can be close enough for a fuzzy match, judging by its length and the pieces
+    fn may_be_within(&self, name: &str, name_length: usize) -> bool {
+        let max_distance = max_distance(self.chars.len().max(name_length));
+        if self.chars.len().abs_diff(name_length) > max_distance {
+            return false;
+        }
+        match self.pieces.get(max_distance) {
+            Some(pieces) => pieces.iter().any(|piece| name.contains(piece.as_str())),
+            None => true,
+        }
+    }
+}
+
+/// Edit distance beyond which a match of the given length can't reach the minimum score
+fn max_distance(longest: usize) -> usize {
+    ((1.0 - MIN_FUZZY_SCORE) * longest as f64).floor() as usize
+}
+
+/// Sort (name, tax_id, score) hits by descending score, then name and tax ID
+pub(crate) fn rank_hits(hits: &mut Vec<(String, i64, f64)>, limit: usize) {
+    hits.sort_by(|a, b| {
+        b.2.partial_cmp(&a.2)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+            .then_with(|| a.1.cmp(&b.1))
+    });
+    hits.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
+    hits.truncate(limit);
+}
+
+/// Edit distance between a and b, or None as soon as it's certain to exceed max_distance
+fn bounded_levenshtein(a: &[char], b: &[char], max_distance: usize) -> Option<usize> {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        // Distances never decrease from one row to the next
+        if current.iter().min().is_some_and(|min| *min > max_distance) {
+            return None;
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[b.len()]).filter(|distance| *distance <= max_distance)
+}
@@ -0,0 +1,555 @@
+//! Cache file handling
+//!
+//! Version 1 files are the plain mibig_taxa cache format without a version field, later versions
+//! store it in `format_version`. Version 2 is a superset of the mibig_taxa format, version 3
+//! replaces its `mappings` with a table of distinct names in `strings` and the indices into it for
+//! each entry in `entries`, see the interned module. Files written by save() end with a checksum,
+//! see the checksum module.
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use mibig_taxa::MibigTaxonError;
+
+use crate::checksum::{self, Checksum, ChecksumReader};
+use crate::compression::{self, Compression};
+use crate::errors::PyMibigTaxonError;
+use crate::interned::{EntryRef, EntryStore, NameIndices};
+use crate::progress::Progress;
+use crate::taxdump::{BuildMetadata, Division, TaxonName, TaxonNode};
+
+/// Version of the cache file format written by save()
+pub(crate) const FORMAT_VERSION: u32 = 3;
+/// Oldest cache file format version load() can still read
+const OLDEST_FORMAT_VERSION: u32 = 1;
+/// First version storing entries with interned names instead of mibig_taxa's mappings
+const INTERNED_FORMAT_VERSION: u32 = 3;
+
+/// Data stored in the cache file on top of the entries and deprecated IDs
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CacheExtras {
+    pub nodes: HashMap<i64, TaxonNode>,
+    pub names: HashMap<i64, Vec<TaxonName>>,
+    /// GenBank divisions by ID, from division.dmp
+    pub divisions: HashMap<i64, Division>,
+    /// Genetic code names by ID, from gencode.dmp
+    pub genetic_codes: HashMap<i64, String>,
+    /// Modification date of the taxdump the cache was built from, as YYYY-MM-DD
+    pub taxdump_date: Option<String>,
+    /// Where and when the cache was built, for caches built with this version or later
+    pub metadata: Option<BuildMetadata>,
+    /// Tax IDs of entries added or changed by hand
+    pub manual: BTreeSet<i64>,
+}
+
+#[derive(Serialize)]
+struct CacheFileRef<'a> {
+    format_version: u32,
+    deprecated_ids: &'a HashMap<i64, i64>,
+    strings: Vec<&'a str>,
+    entries: HashMap<i64, NameIndices>,
+    nodes: &'a HashMap<i64, TaxonNode>,
+    names: &'a HashMap<i64, Vec<TaxonName>>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    divisions: &'a HashMap<i64, Division>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    genetic_codes: &'a HashMap<i64, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    taxdump_date: &'a Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: &'a Option<BuildMetadata>,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    manual: &'a BTreeSet<i64>,
+}
+
+#[derive(Deserialize)]
+struct CacheFile {
+    #[serde(default = "oldest_format_version")]
+    format_version: u32,
+    deprecated_ids: HashMap<i64, i64>,
+    /// Entries of files before version 3
+    #[serde(default, deserialize_with = "deserialize_mappings")]
+    mappings: Option<EntryStore>,
+    #[serde(default, deserialize_with = "deserialize_strings")]
+    strings: Vec<Arc<str>>,
+    entries: Option<HashMap<i64, NameIndices>>,
+    #[serde(default)]
+    nodes: HashMap<i64, TaxonNode>,
+    #[serde(default)]
+    names: HashMap<i64, Vec<TaxonName>>,
+    #[serde(default)]
+    divisions: HashMap<i64, Division>,
+    #[serde(default)]
+    genetic_codes: HashMap<i64, String>,
+    #[serde(default)]
+    taxdump_date: Option<String>,
+    #[serde(default)]
+    metadata: Option<BuildMetadata>,
+    #[serde(default)]
+    manual: BTreeSet<i64>,
+}
+
+/// Only the version of a cache file, to report files that don't parse because they're too new
+#[derive(Deserialize)]
+struct CacheFileVersion {
+    format_version: Option<u32>,
+}
+
+fn oldest_format_version() -> u32 {
+    OLDEST_FORMAT_VERSION
+}
+
+/// An entry in the mibig_taxa format, borrowing the names from the file where possible
+#[derive(Deserialize)]
+struct MappingsEntry<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(borrow)]
+    species: Cow<'a, str>,
+    #[serde(borrow)]
+    genus: Cow<'a, str>,
+    #[serde(borrow)]
+    family: Cow<'a, str>,
+    #[serde(borrow)]
+    order: Cow<'a, str>,
+    #[serde(borrow)]
+    class: Cow<'a, str>,
+    #[serde(borrow)]
+    phylum: Cow<'a, str>,
+    #[serde(borrow)]
+    kingdom: Cow<'a, str>,
+    #[serde(borrow)]
+    superkingdom: Cow<'a, str>,
+}
+
+/// Intern the entries of the mibig_taxa format while parsing them
+///
+/// This is much faster than building NcbiTaxEntry values first, as most names are borrowed from
+/// the file and the table only needs the distinct ones.
+fn deserialize_mappings<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<EntryStore>, D::Error> {
+    struct MappingsVisitor;
+
+    impl<'de> Visitor<'de> for MappingsVisitor {
+        type Value = EntryStore;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a map of tax IDs to entries")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<EntryStore, A::Error> {
+            let mut store = EntryStore::default();
+            while let Some((tax_id, entry)) = map.next_entry::<i64, MappingsEntry>()? {
+                store.insert(EntryRef {
+                    tax_id,
+                    name: &entry.name,
+                    species: &entry.species,
+                    genus: &entry.genus,
+                    family: &entry.family,
+                    order: &entry.order,
+                    class: &entry.class,
+                    phylum: &entry.phylum,
+                    kingdom: &entry.kingdom,
+                    superkingdom: &entry.superkingdom,
+                });
+            }
+            Ok(store)
+        }
+    }
+
+    deserializer.deserialize_map(MappingsVisitor).map(Some)
+}
+
+/// A name in the string table, borrowed from the file where possible
+#[derive(Deserialize)]
+struct TableString<'a>(#[serde(borrow)] Cow<'a, str>);
+
+/// Read the string table straight into the layout of the store
+fn deserialize_strings<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Arc<str>>, D::Error> {
+    struct StringsVisitor;
+
+    impl<'de> Visitor<'de> for StringsVisitor {
+        type Value = Vec<Arc<str>>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a list of names")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<Arc<str>>, A::Error> {
+            let mut strings = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(TableString(value)) = seq.next_element()? {
+                strings.push(Arc::from(value.as_ref()));
+            }
+            Ok(strings)
+        }
+    }
+
+    deserializer.deserialize_seq(StringsVisitor)
+}
+
+pub(crate) fn save(
+    cache: &EntryStore,
+    extras: &CacheExtras,
+    mut output: impl Write,
+) -> Result<usize, MibigTaxonError> {
+    let (strings, entries) = cache.to_parts();
+    let data = CacheFileRef {
+        format_version: FORMAT_VERSION,
+        deprecated_ids: &cache.deprecated_ids,
+        strings,
+        entries,
+        nodes: &extras.nodes,
+        names: &extras.names,
+        divisions: &extras.divisions,
+        genetic_codes: &extras.genetic_codes,
+        taxdump_date: &extras.taxdump_date,
+        metadata: &extras.metadata,
+        manual: &extras.manual,
+    };
+    let mut json_data = serde_json::to_vec(&data)?;
+    checksum::append(&mut json_data);
+    output.write_all(&json_data)?;
+
+    Ok(cache.len())
+}
+
+/// Save to a file, optionally compressed with the level or the default level of the compression
+pub(crate) fn save_path(
+    cache: &EntryStore,
+    extras: &CacheExtras,
+    outfile: &Path,
+    compression: Option<(Compression, Option<i32>)>,
+) -> Result<usize, MibigTaxonError> {
+    let out = fs::File::create(outfile)?;
+    match compression {
+        Some((compression, level)) => {
+            let mut encoder = compression::encoder(out, compression, level)?;
+            let size = save(cache, extras, &mut encoder)?;
+            encoder.finish()?;
+            Ok(size)
+        }
+        None => save(cache, extras, out),
+    }
+}
+
+pub(crate) fn load(mut input: impl Read) -> Result<(EntryStore, CacheExtras), PyMibigTaxonError> {
+    let mut json_data = Vec::new();
+    input
+        .read_to_end(&mut json_data)
+        .map_err(MibigTaxonError::from)?;
+    check_checksum(checksum::check(&json_data))?;
+    let result = serde_json::from_slice(&json_data);
+    into_cache(result, || serde_json::from_slice(&json_data).ok())
+}
+
+/// Load without first reading the whole file into memory
+///
+/// Entries and names are parsed straight into the layout of the EntryStore, the rest of the file
+/// into the CacheExtras, so the peak memory use stays close to the size of the loaded cache. This
+/// is slower than load(). open is called again to find the format version if the file fails to
+/// parse.
+pub(crate) fn load_streaming<R: Read>(
+    open: impl Fn() -> io::Result<R>,
+) -> Result<(EntryStore, CacheExtras), PyMibigTaxonError> {
+    let mut reader = ChecksumReader::new(open().map_err(MibigTaxonError::from)?);
+    let result = serde_json::from_reader(io::BufReader::new(&mut reader));
+    // The checksum is only complete if the whole file was parsed
+    if result.is_ok() {
+        check_checksum(reader.finish())?;
+    }
+    into_cache(result, || {
+        serde_json::from_reader(io::BufReader::new(open().ok()?)).ok()
+    })
+}
+
+/// Open a cache file for reading, decompressing it if needed and reporting the progress
+pub(crate) fn open_path<'a>(
+    infile: &Path,
+    compression: Option<Compression>,
+    progress: &'a Progress,
+    stage: &'static str,
+) -> io::Result<Box<dyn Read + 'a>> {
+    let handle = fs::File::open(infile)?;
+    let size = handle.metadata()?.len();
+    let reader = progress.wrap(stage, handle, size);
+    Ok(match compression {
+        Some(compression) => Box::new(compression::decoder(reader, compression)?),
+        None => Box::new(reader),
+    })
+}
+
+/// Split the parsed cache file, read_version is only used to report files that failed to parse
+fn into_cache(
+    result: serde_json::Result<CacheFile>,
+    read_version: impl FnOnce() -> Option<CacheFileVersion>,
+) -> Result<(EntryStore, CacheExtras), PyMibigTaxonError> {
+    let data = match result {
+        Ok(data) => data,
+        Err(err) if err.is_io() => {
+            return Err(PyMibigTaxonError::from(MibigTaxonError::from(
+                io::Error::from(err),
+            )))
+        }
+        Err(err) if err.is_eof() => {
+            return Err(PyMibigTaxonError::InvalidCacheFile(format!(
+                "the file ends early ({}), it may be truncated",
+                err
+            )))
+        }
+        Err(err) => {
+            if let Some(CacheFileVersion {
+                format_version: Some(version),
+            }) = read_version()
+            {
+                check_version(version)?;
+            }
+            return Err(PyMibigTaxonError::from(MibigTaxonError::from(err)));
+        }
+    };
+    check_version(data.format_version)?;
+
+    let cache = match (data.mappings, data.entries) {
+        (Some(mut cache), None) if data.format_version < INTERNED_FORMAT_VERSION => {
+            cache.deprecated_ids = data.deprecated_ids;
+            cache
+        }
+        (None, Some(entries)) if data.format_version >= INTERNED_FORMAT_VERSION => {
+            EntryStore::from_parts(data.deprecated_ids, data.strings, entries).map_err(
+                |tax_id| {
+                    PyMibigTaxonError::InvalidCacheFile(format!(
+                        "the names of tax ID {} are missing from the string table",
+                        tax_id
+                    ))
+                },
+            )?
+        }
+        _ => {
+            return Err(PyMibigTaxonError::InvalidCacheFile(format!(
+                "the entries don't match format version {}",
+                data.format_version
+            )))
+        }
+    };
+    let extras = CacheExtras {
+        nodes: data.nodes,
+        names: data.names,
+        divisions: data.divisions,
+        genetic_codes: data.genetic_codes,
+        taxdump_date: data.taxdump_date,
+        metadata: data.metadata,
+        manual: data.manual,
+    };
+    Ok((cache, extras))
+}
+
+/// Check the checksum of a (decompressed) cache file, reading it in chunks
+///
+/// Unlike load(), this fails for files without a checksum.
+pub(crate) fn verify(input: impl Read) -> Result<(), PyMibigTaxonError> {
+    let mut reader = ChecksumReader::new(input);
+    io::copy(&mut reader, &mut io::sink()).map_err(MibigTaxonError::from)?;
+    require_checksum(reader.finish())
+}
+
+fn require_checksum(result: Checksum) -> Result<(), PyMibigTaxonError> {
+    if result == Checksum::Missing {
+        return Err(PyMibigTaxonError::InvalidCacheFile(
+            "no checksum, the file is truncated or was written by an older version".to_string(),
+        ));
+    }
+    check_checksum(result)
+}
+
+/// Files without a checksum, e.g. from older versions, can still be loaded
+fn check_checksum(result: Checksum) -> Result<(), PyMibigTaxonError> {
+    match result {
+        Checksum::Mismatch => Err(PyMibigTaxonError::InvalidCacheFile(
+            "checksum mismatch, the file is corrupted".to_string(),
+        )),
+        Checksum::Valid | Checksum::Missing => Ok(()),
+    }
+}
+
+/// Check the magic bytes of a file for a known compression
+pub(crate) fn detect_compression(infile: &Path) -> io::Result<Option<Compression>> {
+    let mut start = Vec::with_capacity(4);
+    fs::File::open(infile)?.take(4).read_to_end(&mut start)?;
+    Ok(Compression::detect(&start))
+}
+
+/// Older formats are migrated on load, newer ones need a newer version of this library
+fn check_version(version: u32) -> Result<(), PyMibigTaxonError> {
+    if !(OLDEST_FORMAT_VERSION..=FORMAT_VERSION).contains(&version) {
+        return Err(PyMibigTaxonError::CacheVersionMismatch {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY_NAMES: &str = r#""name":"Streptomyces coelicolor A3(2)","species":"Streptomyces coelicolor","genus":"Streptomyces","family":"Streptomycetaceae","order":"Kitasatosporales","class":"Actinomycetes","phylum":"Actinomycetota","kingdom":"Unknown","superkingdom":"Bacteria""#;
+
+    fn example_cache() -> (EntryStore, CacheExtras) {
+        let mut cache = EntryStore::default();
+        cache.insert(EntryRef {
+            tax_id: 100226,
+            name: "Streptomyces coelicolor A3(2)",
+            species: "Streptomyces coelicolor",
+            genus: "Streptomyces",
+            family: "Streptomycetaceae",
+            order: "Kitasatosporales",
+            class: "Actinomycetes",
+            phylum: "Actinomycetota",
+            kingdom: "Unknown",
+            superkingdom: "Bacteria",
+        });
+        cache.insert(EntryRef {
+            tax_id: 1883,
+            name: "Streptomyces",
+            species: "Unknown",
+            genus: "Streptomyces",
+            family: "Streptomycetaceae",
+            order: "Kitasatosporales",
+            class: "Actinomycetes",
+            phylum: "Actinomycetota",
+            kingdom: "Unknown",
+            superkingdom: "Bacteria",
+        });
+        cache.deprecated_ids.insert(12345, 100226);
+        let mut extras = CacheExtras {
+            taxdump_date: Some("2024-01-31".to_string()),
+            ..CacheExtras::default()
+        };
+        extras.manual.insert(1883);
+        (cache, extras)
+    }
+
+    fn saved(cache: &EntryStore, extras: &CacheExtras) -> Vec<u8> {
+        let mut data = Vec::new();
+        save(cache, extras, &mut data).unwrap();
+        data
+    }
+
+    fn sorted_entries(cache: &EntryStore) -> Vec<EntryRef<'_>> {
+        let mut entries: Vec<EntryRef> = cache.iter().collect();
+        entries.sort_by_key(|entry| entry.tax_id);
+        entries
+    }
+
+    #[test]
+    fn round_trip() {
+        let (cache, extras) = example_cache();
+        let data = saved(&cache, &extras);
+        assert_eq!(checksum::check(&data), Checksum::Valid);
+
+        let (loaded, loaded_extras) = load(&data[..]).unwrap();
+        assert_eq!(sorted_entries(&loaded), sorted_entries(&cache));
+        assert_eq!(loaded.deprecated_ids, cache.deprecated_ids);
+        assert_eq!(loaded_extras.taxdump_date, extras.taxdump_date);
+        assert_eq!(loaded_extras.manual, extras.manual);
+    }
+
+    #[test]
+    fn round_trip_streaming() {
+        let (cache, extras) = example_cache();
+        let data = saved(&cache, &extras);
+        let (loaded, loaded_extras) = load_streaming(|| Ok(&data[..])).unwrap();
+        assert_eq!(sorted_entries(&loaded), sorted_entries(&cache));
+        assert_eq!(loaded.deprecated_ids, cache.deprecated_ids);
+        assert_eq!(loaded_extras.manual, extras.manual);
+
+        // Escaped names can't be borrowed from the file
+        let data = br#"{"format_version":3,"deprecated_ids":{},"strings":["Strepto\u006dyces","Unknown"],"entries":{"1883":[0,1,0,1,1,1,1,1,1]}}"#;
+        let (cache, _) = load_streaming(|| Ok(&data[..])).unwrap();
+        assert_eq!(cache.entry(1883).name, "Streptomyces");
+        let (cache, _) = load(&data[..]).unwrap();
+        assert_eq!(cache.entry(1883).genus, "Streptomyces");
+    }
+
+    #[test]
+    fn load_version_1() {
+        let data = format!(
+            r#"{{"deprecated_ids":{{"12345":100226}},"mappings":{{"100226":{{"tax_id":100226,{}}}}}}}"#,
+            ENTRY_NAMES
+        );
+        let (cache, extras) = load(data.as_bytes()).unwrap();
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.entry(100226).genus, "Streptomyces");
+        assert_eq!(cache.deprecated_ids.get(&12345), Some(&100226));
+        assert!(extras.nodes.is_empty());
+    }
+
+    #[test]
+    fn load_version_2() {
+        let data = format!(
+            r#"{{"format_version":2,"deprecated_ids":{{}},"mappings":{{"100226":{{"tax_id":100226,{}}}}},"taxdump_date":"2024-01-31"}}"#,
+            ENTRY_NAMES
+        );
+        let (cache, extras) = load(data.as_bytes()).unwrap();
+        assert_eq!(cache.entry(100226).name, "Streptomyces coelicolor A3(2)");
+        assert_eq!(extras.taxdump_date.as_deref(), Some("2024-01-31"));
+    }
+
+    #[test]
+    fn load_version_3() {
+        let data = br#"{"format_version":3,"deprecated_ids":{},"strings":["Streptomyces","Unknown"],"entries":{"1883":[0,1,0,1,1,1,1,1,1]}}"#;
+        let (cache, _) = load(&data[..]).unwrap();
+        assert_eq!(cache.entry(1883).name, "Streptomyces");
+        assert_eq!(cache.entry(1883).species, "Unknown");
+    }
+
+    #[test]
+    fn corrupted_checksum() {
+        let (cache, extras) = example_cache();
+        let mut data = saved(&cache, &extras);
+        let position = data
+            .windows(b"2024-01-31".len())
+            .position(|window| window == b"2024-01-31")
+            .unwrap();
+        data[position + 3] = b'5';
+        assert_eq!(checksum::check(&data), Checksum::Mismatch);
+
+        let err = load(&data[..]).unwrap_err();
+        assert!(matches!(err, PyMibigTaxonError::InvalidCacheFile(_)));
+    }
+
+    #[test]
+    fn string_index_out_of_range() {
+        let data = br#"{"format_version":3,"deprecated_ids":{},"strings":["Streptomyces"],"entries":{"1883":[0,0,0,0,0,0,0,0,4000000000]}}"#;
+        let err = load(&data[..]).unwrap_err();
+        assert!(matches!(err, PyMibigTaxonError::InvalidCacheFile(_)));
+    }
+
+    #[test]
+    fn entries_not_matching_version() {
+        let data = br#"{"format_version":2,"deprecated_ids":{},"strings":[],"entries":{}}"#;
+        let err = load(&data[..]).unwrap_err();
+        assert!(matches!(err, PyMibigTaxonError::InvalidCacheFile(_)));
+    }
+
+    #[test]
+    fn newer_version() {
+        let data = br#"{"format_version":99,"deprecated_ids":{},"future":[]}"#;
+        let err = load(&data[..]).unwrap_err();
+        assert!(matches!(
+            err,
+            PyMibigTaxonError::CacheVersionMismatch { found: 99, .. }
+        ));
+    }
+}
@@ -0,0 +1,498 @@
+//! Parsing for the taxdump files mibig_taxa doesn't handle itself
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use mibig_taxa::{MibigTaxonError, TaxonCache};
+
+use crate::archive;
+use crate::checksum::HashingReader;
+use crate::interned::EntryStore;
+use crate::logging::{self, Level};
+use crate::progress::{Progress, ProgressReader};
+use crate::storage::CacheExtras;
+
+/// Where and when a cache was built, recorded by initialise()
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub(crate) struct BuildMetadata {
+    /// Path of the taxdump or taxdump archive
+    pub taxdump: String,
+    /// Checksum of merged.dmp, in the format of the cache file checksum
+    pub merged_checksum: String,
+    /// Path of the MIBiG data directory
+    pub datadir: String,
+    /// Time of the build, as an RFC 3339 timestamp in UTC
+    pub built_at: String,
+    /// Version of this library
+    pub library_version: String,
+}
+
+/// Tree information from nodes.dmp
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub(crate) struct TaxonNode {
+    pub parent_id: i64,
+    pub rank: String,
+    /// ID of the GenBank division, see division.dmp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub division_id: Option<i64>,
+    /// ID of the genetic code, see gencode.dmp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genetic_code_id: Option<i64>,
+    /// ID of the mitochondrial genetic code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mito_code_id: Option<i64>,
+}
+
+/// GenBank division from division.dmp
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct Division {
+    /// Three letter code, e.g. "BCT"
+    pub code: String,
+    /// e.g. "Bacteria" or "Plants and Fungi"
+    pub name: String,
+}
+
+/// Alternative name from names.dmp
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct TaxonName {
+    pub name: String,
+    pub name_class: String,
+}
+
+/// Name classes from names.dmp that are kept as synonyms
+const SYNONYM_CLASSES: [&str; 4] = [
+    "synonym",
+    "equivalent name",
+    "common name",
+    "genbank common name",
+];
+
+/// Name classes from names.dmp that NCBI keeps former names of a taxon under
+pub(crate) const FORMER_NAME_CLASSES: [&str; 2] = ["synonym", "equivalent name"];
+
+/// Paths to the taxdump files and MIBiG data used to build a cache
+///
+/// If taxdump is a taxdump archive, the other dump paths are the names of files in the archive.
+pub(crate) struct TaxdumpPaths {
+    pub taxdump: PathBuf,
+    pub merged_id_dump: PathBuf,
+    pub datadir: PathBuf,
+    pub nodes_dump: Option<PathBuf>,
+    pub names_dump: Option<PathBuf>,
+    pub division_dump: Option<PathBuf>,
+    pub gencode_dump: Option<PathBuf>,
+}
+
+/// Like TaxonCache::initialise_from_paths, but also read the optional nodes, names, division and
+/// gencode dumps
+///
+/// With a nodes dump, the lineages of all tax IDs are added to the cache as well.
+/// Returns the additional data read from the optional dumps.
+pub(crate) fn initialise(
+    store: &mut EntryStore,
+    paths: TaxdumpPaths,
+    progress: &Progress,
+) -> Result<CacheExtras, MibigTaxonError> {
+    // mibig_taxa reads the taxdump, its entries are interned once they're complete
+    let mut cache = TaxonCache::new();
+    progress.report("datadir", 0, 1)?;
+    let mut taxids = cache.find_taxids(paths.datadir.clone())?;
+    progress.report("datadir", 1, 1)?;
+
+    let dumps = Dumps::new(&paths)?;
+    let open = |path: &Path, stage: &'static str| dumps.open(path, stage, progress);
+
+    let mut nodes: HashMap<i64, TaxonNode> = HashMap::new();
+    if let Some(nodes_dump) = &paths.nodes_dump {
+        let merged_ids = parse_merged(open(&paths.merged_id_dump, "merged")?)?;
+        let all_nodes = parse_nodes(open(nodes_dump, "nodes")?)?;
+
+        let resolved_ids: Vec<i64> = taxids
+            .iter()
+            .map(|tax_id| *merged_ids.get(tax_id).unwrap_or(tax_id))
+            .collect();
+        let merged_count = taxids
+            .iter()
+            .filter(|tax_id| merged_ids.contains_key(tax_id))
+            .count();
+        logging::debug(&format!(
+            "Resolved {} merged tax IDs of MIBiG entries for the lineages",
+            merged_count
+        ));
+
+        for tax_id in resolved_ids {
+            add_lineage(tax_id, &all_nodes, &mut nodes, &mut taxids);
+        }
+    }
+
+    let taxdump_path = match dumps {
+        Dumps::Files => paths.taxdump.as_path(),
+        Dumps::Archive(_) => Path::new(RANKED_LINEAGE_DUMP),
+    };
+    let taxdump = open(taxdump_path, "taxdump")?;
+    let mut mergeddump = HashingReader::new(open(&paths.merged_id_dump, "merged")?);
+    cache.initialise(taxdump, &mut mergeddump, &mut taxids)?;
+    let merged_checksum = mergeddump.finish()?;
+    logging::info(&format!(
+        "Read {} entries and {} merged IDs from the taxdump",
+        cache.mappings.len(),
+        cache.deprecated_ids.len()
+    ));
+    store.extend(cache);
+
+    let names = match &paths.names_dump {
+        Some(names_dump) => parse_names(open(names_dump, "names")?, store)?,
+        None => HashMap::new(),
+    };
+
+    let divisions = match &paths.division_dump {
+        Some(division_dump) => parse_divisions(open(division_dump, "division")?)?,
+        None => HashMap::new(),
+    };
+    let genetic_codes = match &paths.gencode_dump {
+        Some(gencode_dump) => parse_genetic_codes(open(gencode_dump, "gencode")?)?,
+        None => HashMap::new(),
+    };
+
+    Ok(CacheExtras {
+        nodes,
+        names,
+        divisions,
+        genetic_codes,
+        taxdump_date: file_date(&paths.taxdump),
+        metadata: Some(BuildMetadata {
+            taxdump: absolute_path(&paths.taxdump),
+            merged_checksum,
+            datadir: absolute_path(&paths.datadir),
+            built_at: timestamp(SystemTime::now()),
+            library_version: env!("CARGO_PKG_VERSION").to_string(),
+        }),
+        manual: Default::default(),
+    })
+}
+
+/// The absolute path of a file for the build metadata, or the path as given if that fails
+fn absolute_path(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+/// Format a time as YYYY-MM-DDTHH:MM:SSZ
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let time_of_day = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60
+    )
+}
+
+/// Get the modification date of a file as YYYY-MM-DD
+fn file_date(path: &Path) -> Option<String> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date in the proleptic Gregorian calendar
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Where the dump files are read from
+enum Dumps<'a> {
+    Files,
+    /// A taxdump archive, containing the dump files
+    Archive(&'a Path),
+}
+
+impl<'a> Dumps<'a> {
+    fn new(paths: &'a TaxdumpPaths) -> io::Result<Dumps<'a>> {
+        if archive::is_archive(&paths.taxdump)? {
+            Ok(Dumps::Archive(&paths.taxdump))
+        } else {
+            Ok(Dumps::Files)
+        }
+    }
+
+    /// Open a dump file, streaming it from the archive if the taxdump is one
+    fn open<'p>(
+        &self,
+        path: &Path,
+        stage: &'static str,
+        progress: &'p Progress,
+    ) -> io::Result<ProgressReader<'p, Box<dyn Read>>> {
+        let (reader, size): (Box<dyn Read>, u64) = match self {
+            Dumps::Files => {
+                let file = fs::File::open(path)?;
+                let size = file.metadata()?.len();
+                (Box::new(file), size)
+            }
+            Dumps::Archive(archive) => {
+                let (member, size) = archive::open_member(archive, member_name(path))?;
+                (Box::new(member), size)
+            }
+        };
+        Ok(progress.wrap(stage, reader, size))
+    }
+}
+
+/// Name of the taxdump file with the lineages in taxdump archives
+const RANKED_LINEAGE_DUMP: &str = "rankedlineage.dmp";
+
+/// File name of a dump in an archive
+fn member_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+}
+
+fn add_lineage(
+    tax_id: i64,
+    all_nodes: &HashMap<i64, TaxonNode>,
+    nodes: &mut HashMap<i64, TaxonNode>,
+    taxids: &mut HashSet<i64>,
+) {
+    let mut current = tax_id;
+    while let Some(node) = all_nodes.get(&current) {
+        if nodes.contains_key(&current) {
+            break;
+        }
+        nodes.insert(current, node.clone());
+        taxids.insert(current);
+        // The root node is its own parent
+        if node.parent_id == current {
+            break;
+        }
+        current = node.parent_id;
+    }
+}
+
+/// Get the tax IDs from tax_id up to the root
+pub(crate) fn get_lineage(tax_id: i64, nodes: &HashMap<i64, TaxonNode>) -> Vec<i64> {
+    let mut lineage = vec![tax_id];
+    let mut current = tax_id;
+    while let Some(node) = nodes.get(&current) {
+        if node.parent_id == current || lineage.contains(&node.parent_id) {
+            break;
+        }
+        current = node.parent_id;
+        lineage.push(current);
+    }
+    lineage
+}
+
+/// Read the synonyms of all tax IDs in store from names.dmp
+pub(crate) fn parse_names(
+    names_dump: impl Read,
+    store: &EntryStore,
+) -> Result<HashMap<i64, Vec<TaxonName>>, MibigTaxonError> {
+    let mut names: HashMap<i64, Vec<TaxonName>> = HashMap::new();
+    let mut skipped = SkippedLines::new("names.dmp");
+    for (index, line) in io::BufReader::new(names_dump).lines().enumerate() {
+        let line = line?;
+        let parts = split_line(&line, 5);
+        if parts.len() < 4 {
+            skipped.skip(index, &line);
+            continue;
+        }
+        if !SYNONYM_CLASSES.contains(&parts[3]) {
+            continue;
+        }
+
+        let tax_id: i64 = parts[0].parse()?;
+        if !store.contains(tax_id) {
+            continue;
+        }
+        names.entry(tax_id).or_default().push(TaxonName {
+            name: parts[1].to_string(),
+            name_class: parts[3].to_string(),
+        });
+    }
+    skipped.finish();
+    Ok(names)
+}
+
+/// Build a parent ID to child IDs index from the tree information
+pub(crate) fn index_children(nodes: &HashMap<i64, TaxonNode>) -> HashMap<i64, Vec<i64>> {
+    let mut children: HashMap<i64, Vec<i64>> = HashMap::new();
+    for (tax_id, node) in nodes {
+        if node.parent_id != *tax_id {
+            children.entry(node.parent_id).or_default().push(*tax_id);
+        }
+    }
+    for child_ids in children.values_mut() {
+        child_ids.sort_unstable();
+    }
+    children
+}
+
+/// Counts the lines of a dump that have too few columns to use, logging each one
+struct SkippedLines {
+    file: &'static str,
+    count: usize,
+}
+
+impl SkippedLines {
+    fn new(file: &'static str) -> SkippedLines {
+        SkippedLines { file, count: 0 }
+    }
+
+    /// Note a skipped line, by the index from enumerate()
+    fn skip(&mut self, index: usize, line: &str) {
+        // Empty lines, e.g. at the end of the file, aren't malformed
+        if line.trim().is_empty() {
+            return;
+        }
+        self.count += 1;
+        if logging::enabled(Level::Debug) {
+            logging::debug(&format!(
+                "Skipping malformed line {} of {}: {:?}",
+                index + 1,
+                self.file,
+                line
+            ));
+        }
+    }
+
+    fn finish(self) {
+        if self.count > 0 {
+            logging::warning(&format!(
+                "Skipped {} malformed lines of {}",
+                self.count, self.file
+            ));
+        }
+    }
+}
+
+fn split_line(line: &str, count: usize) -> Vec<&str> {
+    line.trim()
+        .splitn(count, '|')
+        .map(|part| part.trim())
+        .collect()
+}
+
+pub(crate) fn parse_nodes(
+    nodes_dump: impl Read,
+) -> Result<HashMap<i64, TaxonNode>, MibigTaxonError> {
+    let mut nodes = HashMap::new();
+    let mut skipped = SkippedLines::new("nodes.dmp");
+    for (index, line) in io::BufReader::new(nodes_dump).lines().enumerate() {
+        let line = line?;
+        let parts = split_line(&line, 10);
+        if parts.len() < 3 {
+            skipped.skip(index, &line);
+            continue;
+        }
+
+        let tax_id: i64 = parts[0].parse()?;
+        // Only the first three columns are needed to build a tree
+        let column = |index: usize| parts.get(index).and_then(|part| part.parse().ok());
+        let node = TaxonNode {
+            parent_id: parts[1].parse()?,
+            rank: parts[2].to_string(),
+            division_id: column(4),
+            genetic_code_id: column(6),
+            mito_code_id: column(8),
+        };
+        nodes.insert(tax_id, node);
+    }
+    skipped.finish();
+    Ok(nodes)
+}
+
+/// Read the GenBank divisions from division.dmp
+pub(crate) fn parse_divisions(
+    division_dump: impl Read,
+) -> Result<HashMap<i64, Division>, MibigTaxonError> {
+    let mut divisions = HashMap::new();
+    let mut skipped = SkippedLines::new("division.dmp");
+    for (index, line) in io::BufReader::new(division_dump).lines().enumerate() {
+        let line = line?;
+        let parts = split_line(&line, 4);
+        if parts.len() < 3 {
+            skipped.skip(index, &line);
+            continue;
+        }
+
+        let division = Division {
+            code: parts[1].to_string(),
+            name: parts[2].to_string(),
+        };
+        divisions.insert(parts[0].parse()?, division);
+    }
+    skipped.finish();
+    Ok(divisions)
+}
+
+/// Read the genetic code names from gencode.dmp
+pub(crate) fn parse_genetic_codes(
+    gencode_dump: impl Read,
+) -> Result<HashMap<i64, String>, MibigTaxonError> {
+    let mut genetic_codes = HashMap::new();
+    let mut skipped = SkippedLines::new("gencode.dmp");
+    for (index, line) in io::BufReader::new(gencode_dump).lines().enumerate() {
+        let line = line?;
+        let parts = split_line(&line, 4);
+        if parts.len() < 3 {
+            skipped.skip(index, &line);
+            continue;
+        }
+
+        genetic_codes.insert(parts[0].parse()?, parts[2].to_string());
+    }
+    skipped.finish();
+    Ok(genetic_codes)
+}
+
+pub(crate) fn parse_merged(
+    merged_id_dump: impl Read,
+) -> Result<HashMap<i64, i64>, MibigTaxonError> {
+    let mut merged_ids = HashMap::new();
+    let mut skipped = SkippedLines::new("merged.dmp");
+    for (index, line) in io::BufReader::new(merged_id_dump).lines().enumerate() {
+        let line = line?;
+        let parts = split_line(&line, 3);
+        if parts.len() < 2 {
+            skipped.skip(index, &line);
+            continue;
+        }
+
+        merged_ids.insert(parts[0].parse()?, parts[1].parse()?);
+    }
+    skipped.finish();
+    Ok(merged_ids)
+}
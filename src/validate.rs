@@ -0,0 +1,62 @@
+//! Checking the taxonomy of MIBiG entries against the cache
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use mibig_taxa::MibigTaxonError;
+
+#[derive(Deserialize)]
+struct MibigEntry {
+    taxonomy: MibigTaxonomy,
+}
+
+#[derive(Deserialize)]
+struct MibigTaxonomy {
+    #[serde(rename = "ncbiTaxId")]
+    ncbi_tax_id: i64,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Taxonomy of a MIBiG entry, as given in its JSON file
+pub(crate) struct EntryTaxonomy {
+    /// File name without the .json extension, usually the BGC accession
+    pub entry: String,
+    pub tax_id: i64,
+    pub name: Option<String>,
+}
+
+/// Read the taxonomy of all MIBiG entries in datadir, sorted by file name
+pub(crate) fn read_datadir(datadir: &Path) -> Result<Vec<EntryTaxonomy>, MibigTaxonError> {
+    let mut paths = Vec::new();
+    for dir_entry in fs::read_dir(datadir)? {
+        let path = dir_entry?.path();
+        if path.extension() == Some("json".as_ref()) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        let content = fs::read_to_string(&path)?;
+        let mibig_entry: MibigEntry = serde_json::from_str(&content)?;
+        let entry = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        entries.push(EntryTaxonomy {
+            entry,
+            tax_id: mibig_entry.taxonomy.ncbi_tax_id,
+            name: mibig_entry.taxonomy.name,
+        });
+    }
+    Ok(entries)
+}
+
+/// Compare organism names ignoring case and surrounding whitespace
+pub(crate) fn same_name(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
@@ -0,0 +1,118 @@
+//! Conversion of whole ID arrays for dataframe workflows
+//!
+//! numpy and pyarrow are optional, so arrays are accessed through the buffer protocol and the
+//! results are converted back with the library the input came from.
+
+use std::collections::HashMap;
+
+use pyo3::buffer::PyBuffer;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+/// Get the IDs from an int64 buffer (e.g. a numpy array), an object with to_numpy() (e.g. a
+/// pyarrow array or pandas series) or any sequence of ints
+///
+/// Nulls in pyarrow arrays and pandas series, and None in sequences, become None. Converting
+/// those to numpy would turn the IDs into floats otherwise.
+pub(crate) fn extract_ids(ids: &PyAny) -> PyResult<Vec<Option<i64>>> {
+    let py = ids.py();
+    if let Ok(buffer) = PyBuffer::<i64>::get(ids) {
+        return Ok(buffer.to_vec(py)?.into_iter().map(Some).collect());
+    }
+    match module_of(ids)?.as_str() {
+        "pyarrow" if ids.hasattr("is_null")? => {
+            let kwargs = PyDict::new(py);
+            // pyarrow refuses to copy otherwise
+            kwargs.set_item("zero_copy_only", false)?;
+            let values =
+                ids.call_method1("fill_null", (0,))?
+                    .call_method("to_numpy", (), Some(kwargs))?;
+            let nulls = ids
+                .call_method0("is_null")?
+                .call_method("to_numpy", (), Some(kwargs))?;
+            return with_nulls(values, nulls);
+        }
+        "pandas" if ids.hasattr("isna")? => {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("dtype", "int64")?;
+            kwargs.set_item("na_value", 0)?;
+            let values = ids.call_method("to_numpy", (), Some(kwargs))?;
+            let nulls = ids.call_method0("isna")?.call_method0("to_numpy")?;
+            return with_nulls(values, nulls);
+        }
+        _ => {}
+    }
+    if ids.hasattr("to_numpy")? {
+        let array = ids.call_method0("to_numpy")?;
+        if let Ok(buffer) = PyBuffer::<i64>::get(array) {
+            return Ok(buffer.to_vec(py)?.into_iter().map(Some).collect());
+        }
+        return array.extract();
+    }
+    ids.extract()
+}
+
+/// Combine a numpy int64 array of IDs with a numpy bool array marking the nulls
+fn with_nulls(values: &PyAny, nulls: &PyAny) -> PyResult<Vec<Option<i64>>> {
+    let py = values.py();
+    let values = PyBuffer::<i64>::get(values)?.to_vec(py)?;
+    // pyo3 buffers don't support bool, but numpy bools are single bytes
+    let nulls = PyBuffer::<u8>::get(nulls.call_method1("view", ("uint8",))?)?.to_vec(py)?;
+    Ok(values
+        .into_iter()
+        .zip(nulls)
+        .map(|(value, null)| if null == 0 { Some(value) } else { None })
+        .collect())
+}
+
+/// Convert values to the array type of input: numpy arrays of objects for numpy input, pyarrow
+/// string arrays for pyarrow input and lists otherwise
+///
+/// Missing values become None. pyarrow results are dictionary-encoded if dictionary is set.
+pub(crate) fn to_array(
+    py: Python,
+    input: &PyAny,
+    values: &[Option<&str>],
+    dictionary: bool,
+) -> PyResult<PyObject> {
+    // The same names show up many times in large tables, only create one string for each
+    let mut strings: HashMap<&str, PyObject> = HashMap::new();
+    let items: Vec<PyObject> = values
+        .iter()
+        .map(|value| match value {
+            Some(value) => strings
+                .entry(value)
+                .or_insert_with(|| value.to_object(py))
+                .clone_ref(py),
+            None => py.None(),
+        })
+        .collect();
+    let list = PyList::new(py, items);
+
+    match module_of(input)?.as_str() {
+        "numpy" => {
+            let numpy = py.import("numpy")?;
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("dtype", numpy.getattr("object_")?)?;
+            Ok(numpy.call_method("array", (list,), Some(kwargs))?.into())
+        }
+        "pyarrow" => {
+            let pyarrow = py.import("pyarrow")?;
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("type", pyarrow.call_method0("string")?)?;
+            let array = pyarrow.call_method("array", (list,), Some(kwargs))?;
+            if dictionary {
+                Ok(array.call_method0("dictionary_encode")?.into())
+            } else {
+                Ok(array.into())
+            }
+        }
+        _ => Ok(list.into()),
+    }
+}
+
+/// Top level package of the type of obj, e.g. "numpy" for numpy.ndarray
+fn module_of(obj: &PyAny) -> PyResult<String> {
+    let module: String = obj.get_type().getattr("__module__")?.extract()?;
+    Ok(module.split('.').next().unwrap_or_default().to_string())
+}
@@ -0,0 +1,120 @@
+//! Serialising changes to a cache
+//!
+//! Changes copy the contents, change the copy and then replace the contents with it. A second
+//! change starting in the meantime would work on the old contents and undo the first one when it
+//! replaces them, so each change holds the write lock from taking its copy until the replacement.
+//! Lookups don't take the lock.
+
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread::{self, ThreadId};
+
+use crate::errors::PyMibigTaxonError;
+
+#[derive(Debug, Default)]
+pub(crate) struct WriteLock {
+    /// Thread holding the lock, if any
+    owner: Mutex<Option<ThreadId>>,
+    released: Condvar,
+}
+
+/// Releases the write lock when dropped
+///
+/// Unlike a MutexGuard, this can be moved out of a closure running with the GIL released.
+#[derive(Debug)]
+pub(crate) struct WriteGuard<'a> {
+    lock: &'a WriteLock,
+}
+
+impl WriteLock {
+    /// Wait until no other thread is changing the cache, and take the lock
+    ///
+    /// Fails instead of waiting forever if this thread holds the lock already, e.g. when a
+    /// progress callback of load() tries to change the cache being loaded.
+    pub fn lock(&self) -> Result<WriteGuard<'_>, PyMibigTaxonError> {
+        let current = thread::current().id();
+        let mut owner = self.owner.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            match *owner {
+                None => break,
+                Some(thread_id) if thread_id == current => {
+                    return Err(PyMibigTaxonError::ChangeInProgress)
+                }
+                Some(_) => {
+                    owner = self
+                        .released
+                        .wait(owner)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        }
+        *owner = Some(current);
+        Ok(WriteGuard { lock: self })
+    }
+
+    /// Take the lock if no thread holds it, without waiting
+    pub fn try_lock(&self) -> Option<WriteGuard<'_>> {
+        let mut owner = self.owner.lock().unwrap_or_else(PoisonError::into_inner);
+        if owner.is_some() {
+            return None;
+        }
+        *owner = Some(thread::current().id());
+        Some(WriteGuard { lock: self })
+    }
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        let mut owner = self
+            .lock
+            .owner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *owner = None;
+        self.lock.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+
+    #[test]
+    fn changes_from_two_threads() {
+        let lock = WriteLock::default();
+        let contents = RwLock::new(Arc::new(Vec::new()));
+
+        // Copy, change with a delay and replace, like the long-running changes do
+        let change = |value: u32| {
+            let _guard = lock.lock().unwrap();
+            let mut copy = Vec::clone(&contents.read().unwrap());
+            thread::sleep(Duration::from_millis(20));
+            copy.push(value);
+            *contents.write().unwrap() = Arc::new(copy);
+        };
+        thread::scope(|scope| {
+            scope.spawn(|| change(1));
+            scope.spawn(|| change(2));
+        });
+
+        let mut values = Vec::clone(&contents.read().unwrap());
+        values.sort_unstable();
+        assert_eq!(values, [1, 2]);
+    }
+
+    #[test]
+    fn lock_held_by_this_thread() {
+        let lock = WriteLock::default();
+        let guard = lock.lock().unwrap();
+        assert!(matches!(
+            lock.lock(),
+            Err(PyMibigTaxonError::ChangeInProgress)
+        ));
+        thread::scope(|scope| {
+            scope.spawn(|| assert!(lock.try_lock().is_none()));
+        });
+        drop(guard);
+        assert!(lock.try_lock().is_some());
+    }
+}
@@ -0,0 +1,64 @@
+import json
+import os
+import sys
+import tempfile
+import unittest
+
+from mibig_taxa import main
+
+ENTRY = {
+    "tax_id": 1883,
+    "name": "Streptomyces",
+    "species": "Unknown",
+    "genus": "Streptomyces",
+    "family": "Streptomycetaceae",
+    "order": "Kitasatosporales",
+    "class": "Actinomycetes",
+    "phylum": "Actinomycetota",
+    "kingdom": "Unknown",
+    "superkingdom": "Bacteria",
+}
+
+
+class MainTest(unittest.TestCase):
+    def setUp(self):
+        self.tmpdir = tempfile.TemporaryDirectory()
+        self.cachefile = os.path.join(self.tmpdir.name, "cache.json")
+        with open(self.cachefile, "w") as handle:
+            json.dump({"deprecated_ids": {}, "mappings": {"1883": ENTRY}}, handle)
+
+    def tearDown(self):
+        self.tmpdir.cleanup()
+
+    def run_main(self, args):
+        """Run main(args), returning the exit status and what it wrote to stdout"""
+        # The tool writes to the file descriptor, not to sys.stdout
+        sys.stdout.flush()
+        saved = os.dup(1)
+        with tempfile.TemporaryFile() as output:
+            os.dup2(output.fileno(), 1)
+            try:
+                status = main(args)
+            finally:
+                os.dup2(saved, 1)
+                os.close(saved)
+            output.seek(0)
+            return status, output.read().decode()
+
+    def test_args_without_program_name(self):
+        status, output = self.run_main(["--cache", self.cachefile, "lookup", "--no-header", "1883"])
+        self.assertEqual(status, 0)
+        self.assertTrue(output.startswith("1883\tStreptomyces\t"), output)
+
+    def test_help(self):
+        status, output = self.run_main(["--help"])
+        self.assertEqual(status, 0)
+        self.assertIn("lookup", output)
+
+    def test_unknown_tax_id(self):
+        status, _ = self.run_main(["--cache", self.cachefile, "lookup", "1"])
+        self.assertEqual(status, 1)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,28 @@
+import unittest
+
+from mibig_taxa import InvalidArgumentError, MibigTaxaError, TaxonCache
+
+
+class InvalidArgumentTest(unittest.TestCase):
+    def setUp(self):
+        self.cache = TaxonCache()
+
+    def test_invalid_option(self):
+        with self.assertRaises(InvalidArgumentError) as context:
+            self.cache.get_name_by_id(1, on_missing="bogus")
+        self.assertIsInstance(context.exception, ValueError)
+        self.assertIsInstance(context.exception, MibigTaxaError)
+        self.assertIn("bogus", str(context.exception))
+
+    def test_invalid_rank(self):
+        with self.assertRaises(InvalidArgumentError) as context:
+            self.cache.set_antismash_mapping_rules([("bogus", "Streptomyces", "bacteria")])
+        self.assertIsInstance(context.exception, ValueError)
+
+    def test_caught_as_value_error(self):
+        with self.assertRaises(ValueError):
+            self.cache.get_name_by_id(1, on_missing="bogus")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,63 @@
+import copy
+import json
+import os
+import pickle
+import tempfile
+import unittest
+
+from mibig_taxa import MibigTaxaError, TaxonCache
+
+ENTRY = {
+    "tax_id": 1883,
+    "name": "Streptomyces",
+    "species": "Unknown",
+    "genus": "Streptomyces",
+    "family": "Streptomycetaceae",
+    "order": "Kitasatosporales",
+    "class": "Actinomycetes",
+    "phylum": "Actinomycetota",
+    "kingdom": "Unknown",
+    "superkingdom": "Bacteria",
+}
+
+
+class ReadOnlyTest(unittest.TestCase):
+    def setUp(self):
+        self.tmpdir = tempfile.TemporaryDirectory()
+        self.cachefile = os.path.join(self.tmpdir.name, "cache.json")
+        with open(self.cachefile, "w") as handle:
+            json.dump({"deprecated_ids": {}, "mappings": {"1883": ENTRY}}, handle)
+
+    def tearDown(self):
+        self.tmpdir.cleanup()
+
+    def assert_readonly(self, cache):
+        self.assertTrue(cache.readonly)
+        self.assertTrue(cache.frozen)
+        self.assertEqual(cache.get_name_by_id(1883), "Streptomyces")
+        with self.assertRaises(MibigTaxaError):
+            cache.save(self.cachefile)
+
+    def test_open_readonly(self):
+        self.assert_readonly(TaxonCache.open_readonly(self.cachefile))
+
+    def test_copies_are_readonly(self):
+        cache = TaxonCache.open_readonly(self.cachefile)
+        self.assert_readonly(copy.copy(cache))
+        self.assert_readonly(copy.deepcopy(cache))
+        self.assert_readonly(cache.freeze())
+
+    def test_pickled_copies_are_readonly(self):
+        cache = TaxonCache.open_readonly(self.cachefile)
+        self.assert_readonly(pickle.loads(pickle.dumps(cache)))
+
+    def test_pickled_writable_cache(self):
+        cache = TaxonCache(self.cachefile)
+        restored = pickle.loads(pickle.dumps(cache))
+        self.assertFalse(restored.readonly)
+        self.assertFalse(restored.frozen)
+        restored.save(self.cachefile)
+
+
+if __name__ == "__main__":
+    unittest.main()